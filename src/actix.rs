@@ -0,0 +1,290 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Actix-web middleware and extractors. The `Token` is stored in the actix-session session, so
+//! `CsrfMiddleware` must be wrapped by a `SessionMiddleware`.
+
+use actix_session::{Session, SessionExt};
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::http::StatusCode;
+use actix_web::{Error, FromRequest, HttpMessage, HttpRequest, ResponseError};
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use std::rc::Rc;
+use crate::body::{extract_token, read_body, BodyKind};
+use crate::config::http_status;
+use crate::{is_safe_method, CsrfConfig, CsrfError, Token};
+
+pub use crate::CsrfToken;
+
+impl ResponseError for CsrfError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(http_status(self)).unwrap_or(StatusCode::FORBIDDEN)
+    }
+}
+
+/// Marker inserted into the request extensions when the middleware has verified the request.
+#[derive(Clone, Copy)]
+struct Verified;
+
+/// Configuration of the middleware, inserted into the request extensions for the extractors.
+#[derive(Clone)]
+struct Config(Rc<CsrfConfig>);
+
+/// Returns the configuration of the middleware, or the default one when it is not used.
+fn request_config(req: &HttpRequest) -> Rc<CsrfConfig> {
+    match req.extensions().get::<Config>() {
+        Some(config) => config.0.clone(),
+        None => Rc::new(CsrfConfig::new()),
+    }
+}
+
+/// `Transform` that stores a `Token` in the session when there is none, and verifies the token
+/// submitted in the configured header or request body field on unsafe requests.
+///
+/// The `CsrfToken` of the request is available to handlers through its extractor.
+#[derive(Clone)]
+pub struct CsrfMiddleware {
+    config: Rc<CsrfConfig>,
+    verify_unsafe: bool,
+}
+impl CsrfMiddleware {
+    /// Creates a new `CsrfMiddleware` using the names in `config`.
+    pub fn new(config: CsrfConfig) -> CsrfMiddleware {
+        CsrfMiddleware {
+            config: Rc::new(config),
+            verify_unsafe: true,
+        }
+    }
+    /// Sets whether unsafe requests are verified by the middleware. When disabled, the
+    /// middleware only issues tokens and verification is left to the `VerifiedCsrf` extractor.
+    pub fn verify_unsafe(mut self, verify: bool) -> CsrfMiddleware {
+        self.verify_unsafe = verify;
+        self
+    }
+}
+impl Default for CsrfMiddleware {
+    fn default() -> CsrfMiddleware {
+        CsrfMiddleware::new(CsrfConfig::new())
+    }
+}
+impl<S, B> Transform<S, ServiceRequest> for CsrfMiddleware
+    where S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+          B: 'static
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = CsrfMiddlewareService<S>;
+    type InitError = ();
+    type Future = Ready<Result<CsrfMiddlewareService<S>, ()>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CsrfMiddlewareService {
+            service: Rc::new(service),
+            config: self.config.clone(),
+            verify_unsafe: self.verify_unsafe,
+        }))
+    }
+}
+
+/// Service created by `CsrfMiddleware`.
+pub struct CsrfMiddlewareService<S> {
+    service: Rc<S>,
+    config: Rc<CsrfConfig>,
+    verify_unsafe: bool,
+}
+impl<S, B> Service<ServiceRequest> for CsrfMiddlewareService<S>
+    where S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+          B: 'static
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<ServiceResponse<EitherBody<B>>, Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let config = self.config.clone();
+        let verify_unsafe = self.verify_unsafe;
+        Box::pin(async move {
+            let token = session_token(&req.get_session(), &config)?;
+            if verify_unsafe && !is_safe_method(req.method().as_str()) {
+                if let Err(e) = verify(&config, &token, &mut req).await {
+                    return Ok(req.error_response(e).map_into_right_body());
+                }
+                req.extensions_mut().insert(Verified);
+            }
+            let csrf_token = CsrfToken::try_new(&token)?.with_encoding(config.encoding());
+            req.extensions_mut().insert(csrf_token);
+            req.extensions_mut().insert(Config(config));
+            service.call(req).await.map(ServiceResponse::map_into_left_body)
+        })
+    }
+}
+
+/// Returns the `Token` stored in the session, storing a new one if there is none.
+fn session_token(session: &Session, config: &CsrfConfig) -> Result<Token, Error> {
+    let stored = session.get::<String>(config.session_key()).ok().flatten();
+    if let Some(token) = stored.and_then(|s| Token::from_base64_str(&s).ok()) {
+        return Ok(token);
+    }
+    let token = Token::try_new()?;
+    session.insert(config.session_key(), token.to_string())?;
+    Ok(token)
+}
+
+/// Verifies the token submitted with an unsafe request, leaving the request body intact.
+async fn verify(config: &CsrfConfig,
+                token: &Token,
+                req: &mut ServiceRequest)
+                -> Result<(), CsrfError> {
+    let header_value = req.headers().get(config.header_name()).map(|v| v.as_bytes());
+    if let Some(submitted) = config.header_token(header_value)? {
+        return config.unmasks_to(&submitted, token);
+    }
+    let kind = req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(BodyKind::from_content_type)
+        .ok_or(CsrfError::MissingToken)?;
+    let body = read_body(config, req.take_payload()).await?;
+    req.set_payload(Payload::from(body.clone()));
+    let submitted = extract_token(config, &kind, &body).await?;
+    config.unmasks_to(&submitted, token)
+}
+
+impl FromRequest for CsrfToken {
+    type Error = Error;
+    type Future = Ready<Result<CsrfToken, Error>>;
+
+    /// Returns the token of the request, storing a new one in the session when `CsrfMiddleware`
+    /// is not used.
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        if let Some(token) = req.extensions().get::<CsrfToken>() {
+            return ready(Ok(token.clone()));
+        }
+        let config = request_config(req);
+        let token = session_token(&req.get_session(), &config).and_then(|token| {
+            Ok(CsrfToken::try_new(&token)?.with_encoding(config.encoding()))
+        });
+        ready(token)
+    }
+}
+
+/// Extractor that succeeds only for verified requests. Requests `CsrfMiddleware` has not
+/// verified, such as safe requests or all requests when `CsrfMiddleware::verify_unsafe` is
+/// disabled, are verified from the configured header against the token in the session.
+#[derive(Clone, Copy, Debug)]
+pub struct VerifiedCsrf;
+impl FromRequest for VerifiedCsrf {
+    type Error = Error;
+    type Future = Ready<Result<VerifiedCsrf, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(verify_request(req).map(|_| VerifiedCsrf).map_err(Error::from))
+    }
+}
+
+fn verify_request(req: &HttpRequest) -> Result<(), CsrfError> {
+    if req.extensions().get::<Verified>().is_some() {
+        return Ok(());
+    }
+    let config = request_config(req);
+    let stored = req.get_session().get::<String>(config.session_key()).ok().flatten();
+    let token = Token::from_base64_str(&stored.ok_or(CsrfError::MissingToken)?)?;
+    let header_value = req.headers().get(config.header_name()).map(|v| v.as_bytes());
+    let submitted = config.header_token(header_value)?.ok_or(CsrfError::MissingToken)?;
+    config.unmasks_to(&submitted, &token)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_session::storage::CookieSessionStore;
+    use actix_session::SessionMiddleware;
+    use actix_web::body::MessageBody;
+    use actix_web::cookie::{Cookie, Key};
+    use actix_web::dev::ServiceResponse;
+    use actix_web::http::header::CONTENT_TYPE;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+    use super::{CsrfMiddleware, CsrfToken, VerifiedCsrf};
+    use crate::{CsrfConfig, PaddedToken, Token, TokenEncoding};
+
+    macro_rules! app {
+        ($middleware:expr) => {
+            test::init_service(App::new()
+                .wrap($middleware)
+                .wrap(SessionMiddleware::new(CookieSessionStore::default(), Key::generate()))
+                .route("/form", web::get().to(|token: CsrfToken| async move { token.to_string() }))
+                .route("/submit", web::post().to(|body: String| async move { body }))
+                .route("/api", web::post().to(|_: VerifiedCsrf| async { "ok" })))
+                .await
+        };
+    }
+
+    /// Returns the session cookie and the token embedded in the form page.
+    async fn form<B: MessageBody>(res: ServiceResponse<B>) -> (Cookie<'static>, PaddedToken) {
+        let cookie = res.response().cookies().next().unwrap().into_owned();
+        let body = test::read_body(res).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        (cookie, PaddedToken::decode(body, TokenEncoding::Base64Url).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn submitted_token_is_verified_by_middleware() {
+        let app = app!(CsrfMiddleware::new(CsrfConfig::new().with_field_name("_csrf")));
+        let req = test::TestRequest::get().uri("/form").to_request();
+        let res = test::call_service(&app, req).await;
+        let (cookie, padded_token) = form(res).await;
+
+        let req = test::TestRequest::post()
+            .uri("/submit")
+            .cookie(cookie.clone())
+            .insert_header(("X-CSRF-Token", padded_token.encode(TokenEncoding::Base64Url)))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+        let form = format!("name=x&_csrf={}", padded_token.encode(TokenEncoding::Base64Url));
+        let req = test::TestRequest::post()
+            .uri("/submit")
+            .cookie(cookie.clone())
+            .insert_header((CONTENT_TYPE, "application/x-www-form-urlencoded"))
+            .set_payload(form.clone())
+            .to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, form.as_bytes());
+
+        let other = PaddedToken::new(&Token::new());
+        let req = test::TestRequest::post()
+            .uri("/submit")
+            .cookie(cookie)
+            .insert_header(("X-CSRF-Token", other.encode(TokenEncoding::Base64Url)))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+    }
+    #[actix_web::test]
+    async fn verified_extractor_checks_header() {
+        let app = app!(CsrfMiddleware::default().verify_unsafe(false));
+        let req = test::TestRequest::get().uri("/form").to_request();
+        let res = test::call_service(&app, req).await;
+        let (cookie, padded_token) = form(res).await;
+
+        let req = test::TestRequest::post().uri("/submit").cookie(cookie.clone()).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        let req = test::TestRequest::post().uri("/api").cookie(cookie.clone()).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+        let req = test::TestRequest::post()
+            .uri("/api")
+            .cookie(cookie)
+            .insert_header(("X-CSRF-Token", padded_token.encode(TokenEncoding::Base64Url)))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    }
+}
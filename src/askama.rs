@@ -0,0 +1,78 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Askama integration. Re-export `csrf_field` from the `filters` module next to the template
+//! to render the hidden form input with `{{ csrf_token|csrf_field }}`, where `csrf_token` is a
+//! `CsrfToken`. The token is rendered in its own encoding. The form field name is taken from a
+//! `CsrfConfig` passed to `Template::render_with_values` under the `csrf_config` key, or the
+//! default one.
+
+use askama::filters::{HtmlSafe, Safe};
+use askama::{Value, Values};
+use std::any::Any;
+use crate::{CsrfConfig, CsrfToken, HiddenInput, MetaTag};
+
+impl HtmlSafe for HiddenInput<'_> {}
+impl HtmlSafe for MetaTag<'_> {}
+
+impl Value for CsrfConfig {
+    fn ref_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+/// Askama filter rendering the hidden form input for a `CsrfToken`.
+#[askama::filter_fn]
+pub fn csrf_field(token: &CsrfToken, values: &dyn Values) -> askama::Result<Safe<String>> {
+    let token = token.to_string();
+    let field = match askama::get_value::<CsrfConfig>(values, "csrf_config") {
+        Ok(config) => config.encoded_hidden_input(&token).to_string(),
+        Err(_) => CsrfConfig::new().encoded_hidden_input(&token).to_string(),
+    };
+    Ok(Safe(field))
+}
+
+#[cfg(test)]
+mod tests {
+    use askama::Template;
+    use crate::{CsrfConfig, CsrfToken, Token, TokenEncoding};
+
+    mod filters {
+        pub use crate::askama::csrf_field;
+    }
+
+    #[derive(Template)]
+    #[template(source = "<form>{{ csrf_token|csrf_field }}</form>", ext = "html")]
+    struct Form {
+        csrf_token: CsrfToken,
+    }
+
+    #[test]
+    fn renders_hidden_input() {
+        let form = Form { csrf_token: CsrfToken::new(&Token::new()) };
+        let padded_token = form.csrf_token.padded_token();
+        assert_eq!(form.render().unwrap(),
+                   format!("<form>{}</form>", CsrfConfig::new().hidden_input(padded_token)));
+        let config = CsrfConfig::new().with_field_name("_csrf");
+        assert_eq!(form.render_with_values(&("csrf_config", config.clone())).unwrap(),
+                   format!("<form>{}</form>", config.hidden_input(padded_token)));
+    }
+    #[test]
+    fn renders_token_in_its_own_encoding() {
+        let csrf_token = CsrfToken::new(&Token::new()).with_encoding(TokenEncoding::Hex);
+        let form = Form { csrf_token: csrf_token.clone() };
+        let config = CsrfConfig::new().with_encoding(TokenEncoding::Crockford32);
+        let expected = config.clone().with_encoding(TokenEncoding::Hex);
+        assert_eq!(form.render_with_values(&("csrf_config", config)).unwrap(),
+                   format!("<form>{}</form>", expected.hidden_input(csrf_token.padded_token())));
+        assert_eq!(form.render().unwrap(),
+                   format!("<form>{}</form>",
+                           CsrfConfig::new().encoded_hidden_input(&csrf_token.to_string())));
+    }
+}
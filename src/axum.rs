@@ -0,0 +1,137 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Axum extractors. Add a `CsrfLayer` to the router to issue and verify tokens.
+
+use axum_core::extract::FromRequestParts;
+use axum_core::response::{IntoResponse, Response};
+use http::request::Parts;
+use http::StatusCode;
+use std::error::Error;
+use std::fmt;
+use crate::tower::Verification;
+use crate::CsrfError;
+
+pub use crate::tower::CsrfLayer;
+pub use crate::CsrfToken;
+
+/// Rejection of the extractors in this module.
+#[derive(Debug)]
+pub enum CsrfRejection {
+    /// No `CsrfLayer` was added to the router.
+    MissingLayer,
+    /// The request failed verification.
+    Failed(CsrfError),
+}
+impl fmt::Display for CsrfRejection {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CsrfRejection::MissingLayer => write!(fmt, "CSRF layer is missing"),
+            CsrfRejection::Failed(ref e) => write!(fmt, "CSRF verification failed: {}", e),
+        }
+    }
+}
+impl Error for CsrfRejection {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            CsrfRejection::MissingLayer => None,
+            CsrfRejection::Failed(ref e) => Some(e),
+        }
+    }
+}
+impl IntoResponse for CsrfRejection {
+    fn into_response(self) -> Response {
+        let status = match self {
+            CsrfRejection::MissingLayer => StatusCode::INTERNAL_SERVER_ERROR,
+            CsrfRejection::Failed(_) => StatusCode::FORBIDDEN,
+        };
+        (status, self.to_string()).into_response()
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for CsrfToken {
+    type Rejection = CsrfRejection;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<CsrfToken, CsrfRejection> {
+        parts.extensions.get::<CsrfToken>().cloned().ok_or(CsrfRejection::MissingLayer)
+    }
+}
+
+/// Extractor that succeeds only for verified requests. Requests the `CsrfLayer` has not verified,
+/// such as safe requests or all requests when `CsrfLayer::verify_unsafe` is disabled, are
+/// verified from the configured header.
+#[derive(Clone, Copy, Debug)]
+pub struct VerifiedCsrf;
+impl<S: Send + Sync> FromRequestParts<S> for VerifiedCsrf {
+    type Rejection = CsrfRejection;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<VerifiedCsrf, CsrfRejection> {
+        let verification = parts.extensions
+            .get::<Verification>()
+            .ok_or(CsrfRejection::MissingLayer)?;
+        verification.verify(&parts.headers).map_err(CsrfRejection::Failed)?;
+        Ok(VerifiedCsrf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::header::COOKIE;
+    use axum::http::{Request, StatusCode};
+    use axum::routing::{get, post};
+    use axum::Router;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+    use super::{CsrfLayer, CsrfToken, VerifiedCsrf};
+    use crate::{DoubleSubmitCookie, PaddedToken, Token, TokenEncoding};
+
+    fn app(layer: CsrfLayer) -> Router {
+        Router::new()
+            .route("/form", get(|token: CsrfToken| async move { token.to_string() }))
+            .route("/submit", post(|_: VerifiedCsrf| async { "ok" }))
+            .layer(layer)
+    }
+
+    #[tokio::test]
+    async fn token_extractor_yields_padded_token_of_cookie() {
+        let (token, _) = DoubleSubmitCookie::new().issue(None);
+        let req = Request::get("/form")
+            .header(COOKIE, format!("csrf_token={}", token.encode(TokenEncoding::Base64Url)))
+            .body(Body::empty())
+            .unwrap();
+        let res = app(CsrfLayer::default()).oneshot(req).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let body = std::str::from_utf8(&body).unwrap();
+        let padded_token = PaddedToken::decode(body, TokenEncoding::Base64Url);
+        assert!(padded_token.unwrap().unmask() == token);
+
+        let router = Router::new().route("/form", get(|_: CsrfToken| async { "" }));
+        let res = router.oneshot(Request::get("/form").body(Body::empty()).unwrap()).await;
+        assert_eq!(res.unwrap().status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    #[tokio::test]
+    async fn verified_extractor_rejects_bad_tokens() {
+        let app = app(CsrfLayer::default().verify_unsafe(false));
+        let (token, padded_token) = DoubleSubmitCookie::new().issue(None);
+        let submit = |submitted: &PaddedToken| {
+            Request::post("/submit")
+                .header(COOKIE, format!("csrf_token={}", token.encode(TokenEncoding::Base64Url)))
+                .header("x-csrf-token", submitted.encode(TokenEncoding::Base64Url))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let res = app.clone().oneshot(submit(&padded_token)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = app.oneshot(submit(&PaddedToken::new(&Token::new()))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"CSRF verification failed: token does not match");
+    }
+}
@@ -0,0 +1,159 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Token extraction from request bodies.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures_util::{Stream, StreamExt};
+use std::convert::Infallible;
+use std::pin::pin;
+use crate::{CsrfConfig, CsrfError};
+
+/// Kind of request body the submitted token can be extracted from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BodyKind {
+    /// `application/x-www-form-urlencoded` body, searched for the configured form field.
+    UrlEncoded,
+    /// `multipart/form-data` body with the given boundary, searched for the part named after
+    /// the configured form field.
+    Multipart(String),
+    /// JSON body, searched at the configured JSON pointer.
+    Json,
+}
+impl BodyKind {
+    /// Returns the kind of body described by a `Content-Type` header, or `None` if tokens
+    /// cannot be extracted from such bodies.
+    pub fn from_content_type(content_type: &str) -> Option<BodyKind> {
+        let mime = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+        match &mime[..] {
+            "application/x-www-form-urlencoded" => Some(BodyKind::UrlEncoded),
+            "multipart/form-data" => {
+                multer::parse_boundary(content_type).ok().map(BodyKind::Multipart)
+            }
+            "application/json" => Some(BodyKind::Json),
+            mime if mime.starts_with("application/") && mime.ends_with("+json") => {
+                Some(BodyKind::Json)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Reads a request body from a stream of chunks, failing as soon as it is larger than the
+/// configured body limit.
+pub async fn read_body<S, D, E>(config: &CsrfConfig, stream: S) -> Result<Bytes, CsrfError>
+    where S: Stream<Item = Result<D, E>>,
+          D: Buf
+{
+    let limit = config.body_limit();
+    let mut stream = pin!(stream);
+    let mut body = BytesMut::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|_| CsrfError::InvalidBody)?;
+        if body.len() + chunk.remaining() > limit {
+            return Err(CsrfError::BodyTooLarge(limit));
+        }
+        body.put(chunk);
+    }
+    Ok(body.freeze())
+}
+
+/// Extracts the submitted token from a buffered request body of the given kind. The body is
+/// not consumed, so the same `Bytes` can be handed on to the handler.
+///
+/// The body limit is not checked here, as the body is already in memory. Use `read_body` to
+/// buffer bodies with the limit applied.
+pub async fn extract_token(config: &CsrfConfig,
+                           kind: &BodyKind,
+                           body: &Bytes)
+                           -> Result<String, CsrfError> {
+    let token = match *kind {
+        BodyKind::UrlEncoded => {
+            form_urlencoded::parse(body)
+                .find(|(name, _)| name == config.field_name())
+                .map(|(_, value)| value.into_owned())
+        }
+        BodyKind::Multipart(ref boundary) => {
+            let body = body.clone();
+            let stream = futures_util::stream::once(async move { Ok::<_, Infallible>(body) });
+            let mut multipart = multer::Multipart::new(stream, boundary.clone());
+            let mut token = None;
+            while let Some(field) = multipart.next_field()
+                .await
+                .map_err(|_| CsrfError::InvalidBody)? {
+                if field.name() == Some(config.field_name()) {
+                    token = Some(field.text().await.map_err(|_| CsrfError::InvalidBody)?);
+                    break;
+                }
+            }
+            token
+        }
+        BodyKind::Json => {
+            let json: serde_json::Value = serde_json::from_slice(body)
+                .map_err(|_| CsrfError::InvalidBody)?;
+            json.pointer(config.json_pointer()).and_then(|v| v.as_str()).map(String::from)
+        }
+    };
+    token.ok_or(CsrfError::MissingToken)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use std::convert::Infallible;
+    use super::{extract_token, read_body, BodyKind};
+    use crate::{CsrfConfig, CsrfError};
+
+    async fn extract(config: &CsrfConfig,
+                     content_type: &str,
+                     body: &str)
+                     -> Result<String, CsrfError> {
+        let kind = BodyKind::from_content_type(content_type).unwrap();
+        extract_token(config, &kind, &Bytes::from(body.to_owned())).await
+    }
+
+    #[tokio::test]
+    async fn extracts_from_urlencoded_and_multipart() {
+        let config = CsrfConfig::new();
+        let form = "a=1&csrf_token=abc%2B%2F%3D&b=2";
+        assert_eq!(extract(&config, "application/x-www-form-urlencoded", form).await.unwrap(),
+                   "abc+/=");
+
+        let multipart = "--X\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a\"\r\n\
+                         \r\ndata\r\n--X\r\n\
+                         Content-Disposition: form-data; name=\"csrf_token\"\r\n\
+                         \r\nabc+/=\r\n--X--\r\n";
+        assert_eq!(extract(&config, "multipart/form-data; boundary=X", multipart).await.unwrap(),
+                   "abc+/=");
+        assert!(matches!(extract(&config, "multipart/form-data; boundary=Y", multipart).await,
+                         Err(CsrfError::InvalidBody)));
+    }
+    #[tokio::test]
+    async fn extracts_from_json_pointer() {
+        let config = CsrfConfig::new().with_json_pointer("/meta/csrf");
+        let json = r#"{"name": "x", "meta": {"csrf": "abc"}}"#;
+        assert_eq!(extract(&config, "application/json; charset=utf-8", json).await.unwrap(), "abc");
+        assert!(matches!(extract(&CsrfConfig::new(), "application/json", json).await,
+                         Err(CsrfError::MissingToken)));
+        assert_eq!(BodyKind::from_content_type("text/plain"), None);
+    }
+    #[tokio::test]
+    async fn body_is_read_up_to_limit() {
+        let config = CsrfConfig::new().with_body_limit(8);
+        let chunks = |chunks: Vec<&'static str>| {
+            let chunks = chunks.into_iter().map(|chunk| Ok::<_, Infallible>(chunk.as_bytes()));
+            futures_util::stream::iter(chunks)
+        };
+        assert_eq!(read_body(&config, chunks(vec!["abcd", "efgh"])).await.unwrap(), "abcdefgh");
+        assert!(matches!(read_body(&config, chunks(vec!["abcd", "efgh", "i"])).await,
+                         Err(CsrfError::BodyTooLarge(8))));
+        let failing = futures_util::stream::iter([Ok(Bytes::from("a")), Err(())]);
+        assert!(matches!(read_body(&config, failing).await, Err(CsrfError::InvalidBody)));
+    }
+}
@@ -0,0 +1,260 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Configuration shared by the framework integrations.
+
+use crate::{Token, TokenEncoding};
+
+/// Value of the `SameSite` attribute of the CSRF cookie.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SameSite {
+    /// The cookie is sent only with same-site requests.
+    Strict,
+    /// The cookie is also sent with cross-site top-level navigations.
+    #[default]
+    Lax,
+    /// The cookie is sent with all requests. Requires a secure cookie.
+    None,
+}
+
+/// Names and cookie attributes used to transport tokens, shared by the framework integrations
+/// so that the names the verifier expects and the ones rendered to clients stay in sync.
+#[derive(Clone, Debug)]
+pub struct CsrfConfig {
+    cookie_name: String,
+    header_name: String,
+    field_name: String,
+    session_key: String,
+    json_pointer: String,
+    cookie_path: String,
+    secure: bool,
+    same_site: SameSite,
+    body_limit: usize,
+    encoding: TokenEncoding,
+}
+impl CsrfConfig {
+    /// Creates a new `CsrfConfig` with the default settings: a secure `csrf_token` cookie with
+    /// path `/` and `SameSite=Lax`, an `X-CSRF-Token` header, a `csrf_token` form field and a
+    /// 64 KiB limit for request bodies searched for the token. JSON bodies are searched at
+    /// `/csrf_token` and integrations storing the token in a server session use the
+    /// `csrf_token` session key. Tokens are encoded in URL-safe base64 without padding.
+    pub fn new() -> CsrfConfig {
+        CsrfConfig {
+            cookie_name: "csrf_token".into(),
+            header_name: "x-csrf-token".into(),
+            field_name: "csrf_token".into(),
+            session_key: "csrf_token".into(),
+            json_pointer: "/csrf_token".into(),
+            cookie_path: "/".into(),
+            secure: true,
+            same_site: SameSite::Lax,
+            body_limit: 64 * 1024,
+            encoding: TokenEncoding::Base64Url,
+        }
+    }
+    /// Sets the name of the cookie holding the `Token`.
+    pub fn with_cookie_name(mut self, name: &str) -> CsrfConfig {
+        self.cookie_name = name.into();
+        self
+    }
+    /// Sets the name of the header carrying the submitted `PaddedToken`.
+    pub fn with_header_name(mut self, name: &str) -> CsrfConfig {
+        self.header_name = name.to_ascii_lowercase();
+        self
+    }
+    /// Sets the name of the form field carrying the submitted `PaddedToken`.
+    pub fn with_field_name(mut self, name: &str) -> CsrfConfig {
+        self.field_name = name.into();
+        self
+    }
+    /// Sets the key of the `Token` in server sessions.
+    pub fn with_session_key(mut self, key: &str) -> CsrfConfig {
+        self.session_key = key.into();
+        self
+    }
+    /// Sets the JSON pointer, such as `/meta/csrf_token`, at which JSON bodies carry the
+    /// submitted `PaddedToken`.
+    pub fn with_json_pointer(mut self, pointer: &str) -> CsrfConfig {
+        self.json_pointer = pointer.into();
+        self
+    }
+    /// Sets the path of the cookie.
+    pub fn with_cookie_path(mut self, path: &str) -> CsrfConfig {
+        self.cookie_path = path.into();
+        self
+    }
+    /// Sets whether the cookie is only sent over HTTPS.
+    pub fn with_secure(mut self, secure: bool) -> CsrfConfig {
+        self.secure = secure;
+        self
+    }
+    /// Sets the `SameSite` attribute of the cookie.
+    pub fn with_same_site(mut self, same_site: SameSite) -> CsrfConfig {
+        self.same_site = same_site;
+        self
+    }
+    /// Sets the maximum size in bytes of request bodies searched for the submitted token.
+    pub fn with_body_limit(mut self, limit: usize) -> CsrfConfig {
+        self.body_limit = limit;
+        self
+    }
+    /// Sets the encoding of tokens in cookies, headers, forms and rendered HTML.
+    pub fn with_encoding(mut self, encoding: TokenEncoding) -> CsrfConfig {
+        self.encoding = encoding;
+        self
+    }
+    /// Returns the name of the cookie holding the `Token`.
+    pub fn cookie_name(&self) -> &str {
+        &self.cookie_name
+    }
+    /// Returns the lowercase name of the header carrying the submitted `PaddedToken`.
+    pub fn header_name(&self) -> &str {
+        &self.header_name
+    }
+    /// Returns the name of the form field carrying the submitted `PaddedToken`.
+    pub fn field_name(&self) -> &str {
+        &self.field_name
+    }
+    /// Returns the path of the cookie.
+    pub fn cookie_path(&self) -> &str {
+        &self.cookie_path
+    }
+    /// Returns whether the cookie is only sent over HTTPS.
+    pub fn secure(&self) -> bool {
+        self.secure
+    }
+    /// Returns the `SameSite` attribute of the cookie.
+    pub fn same_site(&self) -> SameSite {
+        self.same_site
+    }
+    /// Returns the key of the `Token` in server sessions.
+    pub fn session_key(&self) -> &str {
+        &self.session_key
+    }
+    /// Returns the JSON pointer at which JSON bodies carry the submitted `PaddedToken`.
+    pub fn json_pointer(&self) -> &str {
+        &self.json_pointer
+    }
+    /// Returns the maximum size in bytes of request bodies searched for the submitted token.
+    pub fn body_limit(&self) -> usize {
+        self.body_limit
+    }
+    /// Returns the encoding of tokens in cookies, headers, forms and rendered HTML.
+    pub fn encoding(&self) -> TokenEncoding {
+        self.encoding
+    }
+    /// Returns the value of a `Set-Cookie` header that stores `token` in the cookie.
+    pub fn set_cookie(&self, token: &Token) -> String {
+        let same_site = match self.same_site {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        };
+        let mut cookie = format!("{}={}; Path={}; HttpOnly; SameSite={}",
+                                 self.cookie_name,
+                                 token.encode(self.encoding),
+                                 self.cookie_path,
+                                 same_site);
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        cookie
+    }
+    /// Returns the value of the cookie from a `Cookie` header.
+    pub fn cookie_value<'a>(&self, cookie_header: &'a str) -> Option<&'a str> {
+        cookie_header
+            .split(';')
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|&(name, _)| name == self.cookie_name)
+            .map(|(_, value)| value.trim_matches('"'))
+    }
+    /// Returns the token submitted in the value of the configured header, if it was sent. Fails
+    /// with `CsrfError::InvalidEncoding` if the value is not valid UTF-8.
+    #[cfg(any(feature = "tower", feature = "actix-web"))]
+    pub(crate) fn header_token(&self,
+                               header_value: Option<&[u8]>)
+                               -> Result<Option<String>, crate::CsrfError> {
+        header_value
+            .map(|value| {
+                let value = std::str::from_utf8(value).map_err(|_| {
+                    crate::CsrfError::InvalidEncoding(self.encoding)
+                });
+                value.map(String::from)
+            })
+            .transpose()
+    }
+    /// Verifies that `submitted` is a `PaddedToken` of `token` in the configured encoding.
+    #[cfg(any(feature = "actix-web", feature = "rocket"))]
+    pub(crate) fn unmasks_to(&self,
+                             submitted: &str,
+                             token: &Token)
+                             -> Result<(), crate::CsrfError> {
+        if crate::PaddedToken::decode(submitted, self.encoding)?.unmask() != *token {
+            return Err(crate::CsrfError::TokenMismatch);
+        }
+        Ok(())
+    }
+}
+impl Default for CsrfConfig {
+    fn default() -> CsrfConfig {
+        CsrfConfig::new()
+    }
+}
+
+/// Returns the HTTP status code of the response rejecting a request with `error`.
+#[cfg(any(feature = "tower", feature = "actix-web"))]
+pub(crate) fn http_status(error: &crate::CsrfError) -> u16 {
+    match *error {
+        crate::CsrfError::BodyTooLarge(_) => 413,
+        crate::CsrfError::InvalidBody => 400,
+        crate::CsrfError::Rng(_) => 500,
+        _ => 403,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CsrfConfig, SameSite, Token};
+
+    #[test]
+    fn set_cookie_and_parse_it_back() {
+        let token = Token::new();
+        let config = CsrfConfig::new();
+        let set_cookie = config.set_cookie(&token);
+        assert!(set_cookie.ends_with("; Path=/; HttpOnly; SameSite=Lax; Secure"));
+        let value = &set_cookie[..set_cookie.find(';').unwrap()];
+        let header = format!("session=abc; {}; other=1", value);
+        let parsed = config.cookie_value(&header).unwrap();
+        assert!(Token::decode(parsed, config.encoding()).unwrap() == token);
+    }
+    #[test]
+    fn cookie_attributes_are_configurable() {
+        let config = CsrfConfig::new()
+            .with_cookie_name("xsrf")
+            .with_cookie_path("/app")
+            .with_secure(false)
+            .with_same_site(SameSite::Strict);
+        let set_cookie = config.set_cookie(&Token::new());
+        assert!(set_cookie.starts_with("xsrf="));
+        assert!(set_cookie.ends_with("; Path=/app; HttpOnly; SameSite=Strict"));
+        assert_eq!(config.cookie_value("csrf_token=a; xsrf=b"), Some("b"));
+        assert_eq!(config.cookie_value("csrf_token=a"), None);
+    }
+    #[cfg(any(feature = "tower", feature = "actix-web"))]
+    #[test]
+    fn header_token_rejects_invalid_utf8() {
+        use crate::{CsrfError, TokenEncoding};
+
+        let config = CsrfConfig::new();
+        assert_eq!(config.header_token(None).unwrap(), None);
+        assert_eq!(config.header_token(Some(b"abc")).unwrap().as_deref(), Some("abc"));
+        assert!(matches!(config.header_token(Some(b"\xff")),
+                         Err(CsrfError::InvalidEncoding(TokenEncoding::Base64Url))));
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Strict base64 decoding.
+
+use std::borrow::Cow;
+use crate::CsrfError;
+
+/// How strictly base64 encoded tokens are decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum DecodeMode {
+    /// Only the canonical encoding is accepted.
+    #[default]
+    Strict,
+    /// Like `Strict`, but whitespace anywhere in the input is ignored.
+    Lenient,
+}
+
+/// Decodes `input`, rejecting anything but the canonical padded base64 encoding of the result.
+pub(crate) fn decode_base64(input: &str, mode: DecodeMode) -> Result<Vec<u8>, CsrfError> {
+    let input = match mode {
+        DecodeMode::Strict => Cow::Borrowed(input),
+        DecodeMode::Lenient => {
+            Cow::Owned(input.chars().filter(|c| !c.is_ascii_whitespace()).collect())
+        }
+    };
+    let bytes = base64::decode(&input)?;
+    let canonical = base64::encode(&bytes);
+    if canonical != input {
+        if canonical.trim_end_matches('=') == input.trim_end_matches('=') {
+            return Err(CsrfError::InvalidPadding);
+        }
+        return Err(CsrfError::NonCanonicalEncoding);
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode_base64;
+    use crate::CsrfError;
+    use crate::DecodeMode::*;
+
+    #[test]
+    fn canonical_input_is_accepted() {
+        assert_eq!(decode_base64("AQIDBA==", Strict).unwrap(), [1, 2, 3, 4]);
+        assert_eq!(decode_base64("", Strict).unwrap(), [0u8; 0]);
+    }
+    #[test]
+    fn padding_is_checked() {
+        assert!(matches!(decode_base64("AQIDBA", Strict), Err(CsrfError::InvalidPadding)));
+        assert!(matches!(decode_base64("AQIDBA=", Strict), Err(CsrfError::InvalidPadding)));
+        assert!(matches!(decode_base64("AQIDBA===", Strict), Err(CsrfError::InvalidPadding)));
+    }
+    #[test]
+    fn non_canonical_input_is_rejected() {
+        assert!(matches!(decode_base64("AQIDBB==", Strict), Err(CsrfError::NonCanonicalEncoding)));
+        assert!(matches!(decode_base64("AQ=IDBA=", Strict), Err(CsrfError::NonCanonicalEncoding)));
+        assert!(matches!(decode_base64("AQIDBA==!", Strict), Err(CsrfError::InvalidBase64(_))));
+    }
+    #[test]
+    fn whitespace_is_ignored_in_lenient_mode() {
+        assert!(matches!(decode_base64(" AQID\r\nBA== ", Strict),
+                         Err(CsrfError::InvalidBase64(_))));
+        assert_eq!(decode_base64(" AQID\r\nBA== ", Lenient).unwrap(), [1, 2, 3, 4]);
+        assert!(decode_base64(" AQIDBA ", Lenient).is_err());
+    }
+}
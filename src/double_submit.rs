@@ -0,0 +1,168 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Double-submit cookie protection.
+
+use rand::rngs::OsRng;
+use rand::{CryptoRng, RngCore};
+use crate::{CsrfError, PaddedToken, Token, TokenEncoding, TokenLength, TokenSigner};
+
+/// Double-submit cookie strategy. A `Token` is sent to the client in a cookie and a
+/// `PaddedToken` of it in the form or a header, and a request is accepted when the two match.
+///
+/// In signed mode the cookie token is created by a `TokenSigner` and bound to the session
+/// identifier, so that an attacker able to set cookies from a sibling subdomain cannot forge a
+/// matching pair.
+pub struct DoubleSubmitCookie {
+    signer: Option<TokenSigner>,
+}
+impl DoubleSubmitCookie {
+    /// Creates a new unsigned `DoubleSubmitCookie`.
+    pub fn new() -> DoubleSubmitCookie {
+        DoubleSubmitCookie { signer: None }
+    }
+    /// Creates a new `DoubleSubmitCookie` that signs cookie tokens with `key`.
+    pub fn signed(key: &[u8]) -> DoubleSubmitCookie {
+        DoubleSubmitCookie::with_signer(TokenSigner::new(key))
+    }
+    /// Creates a new `DoubleSubmitCookie` that signs cookie tokens with `signer`.
+    pub fn with_signer(signer: TokenSigner) -> DoubleSubmitCookie {
+        DoubleSubmitCookie { signer: Some(signer) }
+    }
+    /// Creates a new cookie token and a `PaddedToken` of it for the form or header.
+    /// `session_id` is ignored in unsigned mode. Panics if the random number generator fails.
+    pub fn issue(&self, session_id: Option<&[u8]>) -> (Token, PaddedToken) {
+        self.try_issue(session_id).unwrap()
+    }
+    /// Creates a new cookie token and a `PaddedToken` of it using operating system's random
+    /// number generator.
+    pub fn try_issue(&self,
+                     session_id: Option<&[u8]>)
+                     -> Result<(Token, PaddedToken), CsrfError> {
+        self.issue_with_rng(session_id, &mut OsRng)
+    }
+    /// Creates a new cookie token and a `PaddedToken` of it using `rng`.
+    pub fn issue_with_rng<R: RngCore + CryptoRng>(&self,
+                                                  session_id: Option<&[u8]>,
+                                                  rng: &mut R)
+                                                  -> Result<(Token, PaddedToken), CsrfError> {
+        let token = match self.signer {
+            Some(ref signer) => signer.sign_with_rng(session_id, rng)?,
+            None => Token::with_rng(TokenLength::default(), rng)?,
+        };
+        let padded_token = PaddedToken::with_rng(&token, rng)?;
+        Ok((token, padded_token))
+    }
+    /// Verifies the base64 encoded cookie token against the base64 encoded `PaddedToken`
+    /// submitted in the form or header. `session_id` is ignored in unsigned mode.
+    pub fn verify(&self,
+                  cookie: &str,
+                  submitted: &str,
+                  session_id: Option<&[u8]>)
+                  -> Result<(), CsrfError> {
+        self.verify_with(cookie, submitted, session_id, TokenEncoding::Base64)
+    }
+    /// Verifies the cookie token against the `PaddedToken` submitted in the form or header, both
+    /// in the given encoding. `session_id` is ignored in unsigned mode.
+    pub fn verify_with(&self,
+                       cookie: &str,
+                       submitted: &str,
+                       session_id: Option<&[u8]>,
+                       encoding: TokenEncoding)
+                       -> Result<(), CsrfError> {
+        let token = self.verify_cookie_with(cookie, session_id, encoding)?;
+        if PaddedToken::decode(submitted, encoding)?.unmask() != token {
+            return Err(CsrfError::TokenMismatch);
+        }
+        Ok(())
+    }
+    /// Decodes the base64 encoded cookie token and, in signed mode, verifies its signature.
+    /// Useful for deciding whether an existing cookie can be kept or a new one must be issued.
+    pub fn verify_cookie(&self,
+                         cookie: &str,
+                         session_id: Option<&[u8]>)
+                         -> Result<Token, CsrfError> {
+        self.verify_cookie_with(cookie, session_id, TokenEncoding::Base64)
+    }
+    /// Decodes the cookie token in the given encoding and, in signed mode, verifies its
+    /// signature.
+    pub fn verify_cookie_with(&self,
+                              cookie: &str,
+                              session_id: Option<&[u8]>,
+                              encoding: TokenEncoding)
+                              -> Result<Token, CsrfError> {
+        let token = Token::decode(cookie, encoding)?;
+        self.verify_cookie_token(&token, session_id)?;
+        Ok(token)
+    }
+    /// Verifies an already decoded cookie token. Only signed tokens can fail verification.
+    pub fn verify_cookie_token(&self,
+                               token: &Token,
+                               session_id: Option<&[u8]>)
+                               -> Result<(), CsrfError> {
+        match self.signer {
+            Some(ref signer) => signer.verify(token, session_id),
+            None => Ok(()),
+        }
+    }
+}
+impl Default for DoubleSubmitCookie {
+    fn default() -> DoubleSubmitCookie {
+        DoubleSubmitCookie::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CsrfError, DoubleSubmitCookie, TokenEncoding};
+
+    #[test]
+    fn matching_pair_is_accepted() {
+        let double_submit = DoubleSubmitCookie::new();
+        let (token, padded_token) = double_submit.issue(None);
+        assert!(double_submit.verify(&token.to_string(), &padded_token.to_string(), None).is_ok());
+        let (other, _) = double_submit.issue(None);
+        assert!(matches!(double_submit.verify(&other.to_string(), &padded_token.to_string(), None),
+                         Err(CsrfError::TokenMismatch)));
+        assert!(matches!(double_submit.verify("", &padded_token.to_string(), None),
+                         Err(CsrfError::InvalidLength(0))));
+    }
+    #[test]
+    fn signed_pair_is_bound_to_session() {
+        let double_submit = DoubleSubmitCookie::signed(b"secret");
+        let (token, padded_token) = double_submit.issue(Some(b"session"));
+        let (cookie, submitted) = (token.to_string(), padded_token.to_string());
+        assert!(double_submit.verify(&cookie, &submitted, Some(b"session")).is_ok());
+        assert!(matches!(double_submit.verify(&cookie, &submitted, Some(b"other")),
+                         Err(CsrfError::BadSignature)));
+    }
+    #[test]
+    fn tossed_cookie_is_rejected_in_signed_mode() {
+        let double_submit = DoubleSubmitCookie::signed(b"secret");
+        let (token, padded_token) = DoubleSubmitCookie::new().issue(None);
+        assert!(matches!(double_submit.verify(&token.to_string(),
+                                              &padded_token.to_string(),
+                                              Some(b"session")),
+                         Err(CsrfError::UnknownKey(_))));
+    }
+    #[test]
+    fn pair_is_verified_in_given_encoding() {
+        let double_submit = DoubleSubmitCookie::signed(b"secret");
+        let (token, padded_token) = double_submit.issue(Some(b"session"));
+        for encoding in [TokenEncoding::Base64Url, TokenEncoding::Hex, TokenEncoding::Crockford32] {
+            let (cookie, submitted) = (token.encode(encoding), padded_token.encode(encoding));
+            assert!(double_submit.verify_with(&cookie, &submitted, Some(b"session"), encoding)
+                .is_ok());
+            assert!(double_submit.verify_cookie_with(&cookie, Some(b"session"), encoding).is_ok());
+        }
+        let cookie = token.encode(TokenEncoding::Hex);
+        assert!(matches!(double_submit.verify_cookie(&cookie, Some(b"session")),
+                         Err(CsrfError::InvalidLength(_))));
+    }
+}
@@ -0,0 +1,185 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Text encodings of tokens.
+
+use base64::Base64Mode;
+use std::borrow::Cow;
+use crate::decode::decode_base64;
+use crate::{CsrfError, DecodeMode};
+
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const HEX: &[u8; 16] = b"0123456789abcdef";
+
+/// Text encoding of tokens. Decoding only accepts the canonical encoding of the result unless
+/// `DecodeMode::Lenient` is used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TokenEncoding {
+    /// Standard base64 with padding, as used by the `Display` implementations.
+    Base64,
+    /// URL-safe base64 without padding, which needs no escaping in cookies and URLs.
+    #[default]
+    Base64Url,
+    /// Lowercase hexadecimal. Lenient decoding also accepts uppercase.
+    Hex,
+    /// Crockford's base32 in uppercase, without padding or check symbol. Lenient decoding also
+    /// accepts lowercase, hyphens and the `I`, `L` and `O` aliases.
+    Crockford32,
+}
+impl TokenEncoding {
+    /// Encodes `bytes`.
+    pub fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            TokenEncoding::Base64 => base64::encode(bytes),
+            TokenEncoding::Base64Url => {
+                let mut encoded = base64::encode_mode(bytes, Base64Mode::UrlSafe);
+                encoded.truncate(encoded.trim_end_matches('=').len());
+                encoded
+            }
+            TokenEncoding::Hex => {
+                bytes.iter()
+                    .flat_map(|b| [HEX[(b >> 4) as usize], HEX[(b & 0xf) as usize]])
+                    .map(char::from)
+                    .collect()
+            }
+            TokenEncoding::Crockford32 => {
+                let mut encoded = String::with_capacity((bytes.len() * 8).div_ceil(5));
+                let (mut buffer, mut bits) = (0u16, 0);
+                for &b in bytes {
+                    buffer = buffer << 8 | u16::from(b);
+                    bits += 8;
+                    while bits >= 5 {
+                        bits -= 5;
+                        encoded.push(char::from(CROCKFORD[(buffer >> bits & 0x1f) as usize]));
+                    }
+                }
+                if bits > 0 {
+                    encoded.push(char::from(CROCKFORD[(buffer << (5 - bits) & 0x1f) as usize]));
+                }
+                encoded
+            }
+        }
+    }
+    /// Decodes `input`, rejecting anything but the canonical encoding of the result.
+    pub fn decode(self, input: &str) -> Result<Vec<u8>, CsrfError> {
+        self.decode_with(input, DecodeMode::Strict)
+    }
+    /// Decodes `input` using the given `DecodeMode`.
+    pub fn decode_with(self, input: &str, mode: DecodeMode) -> Result<Vec<u8>, CsrfError> {
+        if self == TokenEncoding::Base64 {
+            return decode_base64(input, mode);
+        }
+        let input = match mode {
+            DecodeMode::Strict => Cow::Borrowed(input),
+            DecodeMode::Lenient => Cow::Owned(self.normalize(input)),
+        };
+        let bytes = match self {
+            TokenEncoding::Base64 => unreachable!(),
+            TokenEncoding::Base64Url => {
+                if input.contains('=') {
+                    return Err(CsrfError::InvalidPadding);
+                }
+                if input.contains(['+', '/']) || input.len() % 4 == 1 {
+                    return Err(CsrfError::InvalidEncoding(self));
+                }
+                let padding = &"=="[..(4 - input.len() % 4) % 4];
+                base64::decode_mode(&format!("{}{}", input, padding), Base64Mode::UrlSafe)?
+            }
+            TokenEncoding::Hex => {
+                if input.len() % 2 != 0 {
+                    return Err(CsrfError::InvalidEncoding(self));
+                }
+                let digits = input.chars()
+                    .map(|c| c.to_digit(16).ok_or(CsrfError::InvalidEncoding(self)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                digits.chunks(2).map(|pair| (pair[0] << 4 | pair[1]) as u8).collect()
+            }
+            TokenEncoding::Crockford32 => {
+                if [1, 3, 6].contains(&(input.len() % 8)) {
+                    return Err(CsrfError::InvalidEncoding(self));
+                }
+                let mut bytes = Vec::with_capacity(input.len() * 5 / 8);
+                let (mut buffer, mut bits) = (0u16, 0);
+                for c in input.bytes() {
+                    let value = CROCKFORD.iter()
+                        .position(|&symbol| symbol == c)
+                        .ok_or(CsrfError::InvalidEncoding(self))?;
+                    buffer = buffer << 5 | value as u16;
+                    bits += 5;
+                    if bits >= 8 {
+                        bits -= 8;
+                        bytes.push((buffer >> bits) as u8);
+                    }
+                }
+                bytes
+            }
+        };
+        if self.encode(&bytes) != input {
+            return Err(CsrfError::NonCanonicalEncoding);
+        }
+        Ok(bytes)
+    }
+    /// Removes whitespace and maps accepted variants to the canonical symbols.
+    fn normalize(self, input: &str) -> String {
+        let chars = input.chars().filter(|c| !c.is_ascii_whitespace());
+        match self {
+            TokenEncoding::Hex => chars.map(|c| c.to_ascii_lowercase()).collect(),
+            TokenEncoding::Crockford32 => {
+                chars.filter(|&c| c != '-')
+                    .map(|c| {
+                        match c.to_ascii_uppercase() {
+                            'I' | 'L' => '1',
+                            'O' => '0',
+                            c => c,
+                        }
+                    })
+                    .collect()
+            }
+            _ => chars.collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CsrfError, DecodeMode, TokenEncoding};
+    use crate::TokenEncoding::*;
+
+    #[test]
+    fn known_vectors() {
+        let bytes = [0xfb, 0xff, 0x00, 0x10, 0x20];
+        assert_eq!(Base64.encode(&bytes), "+/8AECA=");
+        assert_eq!(Base64Url.encode(&bytes), "-_8AECA");
+        assert_eq!(Hex.encode(&bytes), "fbff001020");
+        assert_eq!(Crockford32.encode(&bytes), "ZFZG0410");
+        for encoding in [Base64, Base64Url, Hex, Crockford32] {
+            assert_eq!(encoding.decode(&encoding.encode(&bytes)).unwrap(), bytes);
+            assert_eq!(encoding.decode(&encoding.encode(&bytes[..4])).unwrap(), bytes[..4]);
+        }
+    }
+    #[test]
+    fn non_canonical_input_is_rejected() {
+        assert!(matches!(Base64Url.decode("-_8AECA="), Err(CsrfError::InvalidPadding)));
+        assert!(matches!(Base64Url.decode("+/8AECA"), Err(CsrfError::InvalidEncoding(_))));
+        assert!(matches!(Base64Url.decode("-_8AECB"), Err(CsrfError::NonCanonicalEncoding)));
+        assert!(matches!(Hex.decode("FBFF"), Err(CsrfError::NonCanonicalEncoding)));
+        assert!(matches!(Hex.decode("fbf"), Err(CsrfError::InvalidEncoding(Hex))));
+        assert!(matches!(Crockford32.decode("ZFZG041"), Err(CsrfError::NonCanonicalEncoding)));
+        assert!(matches!(Crockford32.decode("ZFZG041U"), Err(CsrfError::InvalidEncoding(_))));
+    }
+    #[test]
+    fn lenient_mode_accepts_variants() {
+        let lenient = |encoding: TokenEncoding, input| {
+            encoding.decode_with(input, DecodeMode::Lenient)
+        };
+        assert_eq!(lenient(Hex, "FB FF\n").unwrap(), [0xfb, 0xff]);
+        assert_eq!(lenient(Crockford32, "zfzg-o41o").unwrap(), [0xfb, 0xff, 0x00, 0x10, 0x20]);
+        assert!(matches!(lenient(Base64Url, "-_8A ECA=\n"), Err(CsrfError::InvalidPadding)));
+    }
+}
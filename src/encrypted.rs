@@ -0,0 +1,222 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Encrypted token pattern using ChaCha20-Poly1305.
+
+use chacha20poly1305::aead::{Aead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use constant_time_eq::constant_time_eq;
+use crate::decode::decode_base64;
+use crate::expiry::SystemClock;
+use crate::keyring::KeyId;
+use rand::rngs::OsRng;
+use rand::{CryptoRng, RngCore};
+use std::fmt;
+use std::time::Duration;
+use crate::{Clock, CsrfError, DecodeMode, ExpiryPolicy, Keyring};
+
+pub(crate) const KEY_ID_LEN: usize = 4;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const TIMESTAMP_LEN: usize = 8;
+
+/// A token carrying a session identifier and its issue time, sealed with ChaCha20-Poly1305.
+/// Every `EncryptedToken` is different even for the same session, so it can be sent to the
+/// client as is without a `PaddedToken`.
+#[derive(Debug)]
+pub struct EncryptedToken(Vec<u8>);
+impl EncryptedToken {
+    /// Creates a new `EncryptedToken` from a base64 encoded string
+    pub fn from_base64_str(base64: &str) -> Result<EncryptedToken, CsrfError> {
+        EncryptedToken::from_bytes(&decode_base64(base64, DecodeMode::Strict)?)
+    }
+    /// Creates a new `EncryptedToken` from its byte representation, the big-endian key id
+    /// followed by the nonce and the ciphertext.
+    pub fn from_bytes(bytes: &[u8]) -> Result<EncryptedToken, CsrfError> {
+        if bytes.len() < KEY_ID_LEN + NONCE_LEN + TIMESTAMP_LEN + TAG_LEN {
+            return Err(CsrfError::InvalidLength(bytes.len()));
+        }
+        Ok(EncryptedToken(bytes.to_vec()))
+    }
+    /// Returns the byte representation of the `EncryptedToken`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.clone()
+    }
+    /// Returns the id of the key the token was sealed with.
+    pub fn key_id(&self) -> KeyId {
+        let mut id = [0u8; KEY_ID_LEN];
+        id.copy_from_slice(&self.0[..KEY_ID_LEN]);
+        KeyId::from_be_bytes(id)
+    }
+}
+impl fmt::Display for EncryptedToken {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", base64::encode(&self.0))
+    }
+}
+
+/// Seals and opens `EncryptedToken`s with a server key, so that tokens can be verified by any
+/// server holding the key without a shared session store.
+///
+/// Tokens carry the id of the key they were sealed with, which is authenticated along with the
+/// token contents.
+pub struct TokenCipher<C = SystemClock> {
+    keys: Keyring<[u8; 32]>,
+    expiry: ExpiryPolicy<C>,
+}
+impl TokenCipher<SystemClock> {
+    /// Creates a new `TokenCipher` using a 256-bit server key. Tokens older than `max_age` are
+    /// rejected.
+    pub fn new(key: &[u8; 32], max_age: Duration) -> TokenCipher<SystemClock> {
+        TokenCipher::with_expiry(key, ExpiryPolicy::new(max_age))
+    }
+}
+impl<C: Clock> TokenCipher<C> {
+    /// Creates a new `TokenCipher` using a 256-bit server key and the given `ExpiryPolicy`.
+    pub fn with_expiry(key: &[u8; 32], expiry: ExpiryPolicy<C>) -> TokenCipher<C> {
+        TokenCipher::with_keyring(Keyring::new(0, *key), expiry)
+    }
+    /// Creates a new `TokenCipher` using a `Keyring` of 256-bit server keys and the given
+    /// `ExpiryPolicy`.
+    pub fn with_keyring(keys: Keyring<[u8; 32]>, expiry: ExpiryPolicy<C>) -> TokenCipher<C> {
+        TokenCipher { keys, expiry }
+    }
+    /// Returns the `Keyring` of this cipher.
+    pub fn keyring(&self) -> &Keyring<[u8; 32]> {
+        &self.keys
+    }
+    /// Returns the `Keyring` of this cipher for rotating keys.
+    pub fn keyring_mut(&mut self) -> &mut Keyring<[u8; 32]> {
+        &mut self.keys
+    }
+    /// Creates a new `EncryptedToken` bound to `session_id`.
+    /// Panics if the random number generator fails.
+    pub fn seal(&self, session_id: &[u8]) -> EncryptedToken {
+        self.try_seal(session_id).unwrap()
+    }
+    /// Creates a new `EncryptedToken` bound to `session_id` using operating system's random
+    /// number generator.
+    pub fn try_seal(&self, session_id: &[u8]) -> Result<EncryptedToken, CsrfError> {
+        self.seal_with_rng(session_id, &mut OsRng)
+    }
+    /// Creates a new `EncryptedToken` bound to `session_id` using `rng` for the nonce.
+    pub fn seal_with_rng<R: RngCore + CryptoRng>(&self,
+                                                 session_id: &[u8],
+                                                 rng: &mut R)
+                                                 -> Result<EncryptedToken, CsrfError> {
+        let mut plaintext = Vec::with_capacity(TIMESTAMP_LEN + session_id.len());
+        plaintext.extend_from_slice(&self.expiry.now().to_be_bytes());
+        plaintext.extend_from_slice(session_id);
+
+        let (key_id, key) = self.keys.active();
+        let mut nonce = [0u8; NONCE_LEN];
+        rng.try_fill_bytes(&mut nonce).map_err(CsrfError::Rng)?;
+        let key_id = key_id.to_be_bytes();
+        let payload = Payload {
+            msg: &plaintext,
+            aad: &key_id,
+        };
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key))
+            .encrypt(Nonce::from_slice(&nonce), payload)
+            .expect("encrypting a token cannot fail");
+        let mut bytes = key_id.to_vec();
+        bytes.extend_from_slice(&nonce);
+        bytes.extend_from_slice(&ciphertext);
+        Ok(EncryptedToken(bytes))
+    }
+    /// Verifies that `token` was sealed with this cipher's key for `session_id` and that it has
+    /// not expired.
+    pub fn verify(&self, token: &EncryptedToken, session_id: &[u8]) -> Result<(), CsrfError> {
+        if token.0.len() < KEY_ID_LEN + NONCE_LEN + TIMESTAMP_LEN + TAG_LEN {
+            return Err(CsrfError::InvalidLength(token.0.len()));
+        }
+        let (key_id, rest) = token.0.split_at(KEY_ID_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let id = token.key_id();
+        let key = self.keys.get(id).ok_or(CsrfError::UnknownKey(id))?;
+        let payload = Payload {
+            msg: ciphertext,
+            aad: key_id,
+        };
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(key))
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| CsrfError::BadSignature)?;
+        let (timestamp, sealed_session_id) = plaintext.split_at(TIMESTAMP_LEN);
+        if !constant_time_eq(sealed_session_id, session_id) {
+            return Err(CsrfError::TokenMismatch);
+        }
+        let mut issued_at = [0u8; TIMESTAMP_LEN];
+        issued_at.copy_from_slice(timestamp);
+        if !self.expiry.is_fresh_at(u64::from_be_bytes(issued_at)) {
+            return Err(CsrfError::Expired);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KEY_ID_LEN, NONCE_LEN};
+    use std::time::Duration;
+    use crate::expiry::ManualClock;
+    use crate::{CsrfError, EncryptedToken, ExpiryPolicy, Keyring, TokenCipher};
+
+    const KEY: [u8; 32] = [7; 32];
+    /// Sealed with `KEY` as key id 0x01020304, a nonce of nines, issue time 1000 and `session`.
+    const SEALED: &str = "AQIDBAkJCQkJCQkJCQkJCfx5+9fkDoZNrg19S6mIynY0dfSat0GZFZ8GNJHNGt8=";
+
+    #[test]
+    fn byte_layout_matches_fixed_vector() {
+        let token = EncryptedToken::from_base64_str(SEALED).unwrap();
+        assert_eq!(token.key_id(), 0x01020304);
+        assert_eq!(&token.to_bytes()[..KEY_ID_LEN + NONCE_LEN],
+                   [1, 2, 3, 4, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9]);
+        let clock = ManualClock::new(1000);
+        let expiry = ExpiryPolicy::with_clock(Duration::from_secs(60), &clock);
+        let cipher = TokenCipher::with_keyring(Keyring::new(0x01020304, KEY), expiry);
+        assert!(cipher.verify(&token, b"session").is_ok());
+        clock.set(1061);
+        assert!(matches!(cipher.verify(&token, b"session"), Err(CsrfError::Expired)));
+    }
+    #[test]
+    fn seal_and_verify() {
+        let cipher = TokenCipher::new(&KEY, Duration::from_secs(60));
+        let token = cipher.seal(b"session");
+        let decoded = EncryptedToken::from_base64_str(&token.to_string()).unwrap();
+        assert!(cipher.verify(&decoded, b"session").is_ok());
+        assert!(cipher.seal(b"session").to_string() != token.to_string());
+    }
+    #[test]
+    fn wrong_key_or_session_is_rejected() {
+        let cipher = TokenCipher::new(&KEY, Duration::from_secs(60));
+        let token = cipher.seal(b"session");
+        assert!(matches!(cipher.verify(&token, b"other"), Err(CsrfError::TokenMismatch)));
+        assert!(matches!(TokenCipher::new(&[8; 32], Duration::from_secs(60))
+                             .verify(&token, b"session"),
+                         Err(CsrfError::BadSignature)));
+    }
+    #[test]
+    fn tampered_token_is_rejected() {
+        let cipher = TokenCipher::new(&KEY, Duration::from_secs(60));
+        let mut token = cipher.seal(b"session");
+        token.0[KEY_ID_LEN + NONCE_LEN] ^= 1;
+        assert!(matches!(cipher.verify(&token, b"session"), Err(CsrfError::BadSignature)));
+    }
+    #[test]
+    fn expired_token_is_rejected() {
+        let clock = ManualClock::new(1000);
+        let expiry = ExpiryPolicy::with_clock(Duration::from_secs(60), &clock);
+        let cipher = TokenCipher::with_expiry(&KEY, expiry);
+        let token = cipher.seal(b"session");
+        clock.set(1060);
+        assert!(cipher.verify(&token, b"session").is_ok());
+        clock.set(1061);
+        assert!(matches!(cipher.verify(&token, b"session"), Err(CsrfError::Expired)));
+    }
+}
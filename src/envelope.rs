@@ -0,0 +1,305 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Versioned, self-describing token wire format.
+
+use crate::decode::decode_base64;
+use crate::encrypted::KEY_ID_LEN;
+use crate::keyring::KeyId;
+use std::fmt;
+use crate::{CsrfError, DecodeMode, EncryptedToken, PaddedToken, TimedToken, Token, TokenSigner};
+
+const HEADER_LEN: usize = 3;
+const FLAG_KEY_ID: u8 = 0x01;
+
+/// Kind of token carried in an `Envelope`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TokenKind {
+    /// A `Token`.
+    Token,
+    /// A `PaddedToken`.
+    PaddedToken,
+    /// A `TimedToken`.
+    TimedToken,
+    /// An `EncryptedToken`.
+    EncryptedToken,
+    /// A `Token` signed by a `TokenSigner`.
+    SignedToken,
+}
+impl TokenKind {
+    fn to_byte(self) -> u8 {
+        match self {
+            TokenKind::Token => 1,
+            TokenKind::PaddedToken => 2,
+            TokenKind::TimedToken => 3,
+            TokenKind::EncryptedToken => 4,
+            TokenKind::SignedToken => 5,
+        }
+    }
+    fn from_byte(byte: u8) -> Result<TokenKind, CsrfError> {
+        match byte {
+            1 => Ok(TokenKind::Token),
+            2 => Ok(TokenKind::PaddedToken),
+            3 => Ok(TokenKind::TimedToken),
+            4 => Ok(TokenKind::EncryptedToken),
+            5 => Ok(TokenKind::SignedToken),
+            _ => Err(CsrfError::UnknownTokenKind(byte)),
+        }
+    }
+}
+
+/// A token decoded from an `Envelope`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum AnyToken {
+    /// A `Token`.
+    Token(Token),
+    /// A `PaddedToken`.
+    PaddedToken(PaddedToken),
+    /// A `TimedToken`.
+    TimedToken(TimedToken),
+    /// An `EncryptedToken`.
+    EncryptedToken(EncryptedToken),
+    /// A `Token` signed by a `TokenSigner`.
+    SignedToken(Token),
+}
+
+/// Versioned wrapper that lets different kinds of tokens share one wire format.
+///
+/// Version 1 of the format is a version byte, a token kind byte and a flags byte, followed by a
+/// big-endian 32-bit key id if the lowest flag bit is set, followed by the token payload.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Envelope {
+    kind: TokenKind,
+    key_id: Option<KeyId>,
+    payload: Vec<u8>,
+}
+impl Envelope {
+    /// The version of the format written by this library.
+    pub const VERSION: u8 = 1;
+
+    /// Creates a new `Envelope`.
+    pub fn new(kind: TokenKind, key_id: Option<KeyId>, payload: Vec<u8>) -> Envelope {
+        Envelope {
+            kind,
+            key_id,
+            payload,
+        }
+    }
+    /// Creates a new `Envelope` for a `Token` signed by a `TokenSigner`, carrying the id of the
+    /// signing key.
+    pub fn signed(token: &Token) -> Result<Envelope, CsrfError> {
+        let key_id = TokenSigner::key_id(token)?;
+        let payload = token.as_ref()[KEY_ID_LEN..].to_vec();
+        Ok(Envelope::new(TokenKind::SignedToken, Some(key_id), payload))
+    }
+    /// Returns the kind of the token in the envelope.
+    pub fn kind(&self) -> TokenKind {
+        self.kind
+    }
+    /// Returns the id of the key the token was created with, if any.
+    pub fn key_id(&self) -> Option<KeyId> {
+        self.key_id
+    }
+    /// Returns the token payload.
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+    /// Creates a new `Envelope` from a base64 encoded string.
+    pub fn from_base64_str(base64: &str) -> Result<Envelope, CsrfError> {
+        Envelope::from_bytes(&decode_base64(base64, DecodeMode::Strict)?)
+    }
+    /// Creates a new `Envelope` from its byte representation.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Envelope, CsrfError> {
+        match bytes.first() {
+            Some(&Envelope::VERSION) => Envelope::from_v1_bytes(bytes),
+            Some(&version) => Err(CsrfError::UnsupportedVersion(version)),
+            None => Err(CsrfError::InvalidLength(0)),
+        }
+    }
+    fn from_v1_bytes(bytes: &[u8]) -> Result<Envelope, CsrfError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(CsrfError::InvalidLength(bytes.len()));
+        }
+        let kind = TokenKind::from_byte(bytes[1])?;
+        let flags = bytes[2];
+        if flags & !FLAG_KEY_ID != 0 {
+            return Err(CsrfError::MalformedEnvelope);
+        }
+        let mut payload = &bytes[HEADER_LEN..];
+        let key_id = if flags & FLAG_KEY_ID != 0 {
+            if payload.len() < KEY_ID_LEN {
+                return Err(CsrfError::InvalidLength(bytes.len()));
+            }
+            let mut id = [0u8; KEY_ID_LEN];
+            id.copy_from_slice(&payload[..KEY_ID_LEN]);
+            payload = &payload[KEY_ID_LEN..];
+            Some(KeyId::from_be_bytes(id))
+        } else {
+            None
+        };
+        Ok(Envelope::new(kind, key_id, payload.to_vec()))
+    }
+    /// Returns the byte representation of the `Envelope`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let flags = if self.key_id.is_some() { FLAG_KEY_ID } else { 0 };
+        let mut bytes = vec![Envelope::VERSION, self.kind.to_byte(), flags];
+        if let Some(key_id) = self.key_id {
+            bytes.extend_from_slice(&key_id.to_be_bytes());
+        }
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+    /// Decodes the token in the envelope.
+    pub fn open(&self) -> Result<AnyToken, CsrfError> {
+        match (self.kind, self.key_id) {
+            (TokenKind::Token, None) => Token::from_bytes(&self.payload).map(AnyToken::Token),
+            (TokenKind::PaddedToken, None) => {
+                PaddedToken::from_bytes(&self.payload).map(AnyToken::PaddedToken)
+            }
+            (TokenKind::TimedToken, None) => {
+                TimedToken::from_bytes(&self.payload).map(AnyToken::TimedToken)
+            }
+            (TokenKind::EncryptedToken, Some(key_id)) => {
+                let mut bytes = key_id.to_be_bytes().to_vec();
+                bytes.extend_from_slice(&self.payload);
+                EncryptedToken::from_bytes(&bytes).map(AnyToken::EncryptedToken)
+            }
+            (TokenKind::SignedToken, Some(key_id)) => {
+                let mut bytes = key_id.to_be_bytes().to_vec();
+                bytes.extend_from_slice(&self.payload);
+                let token = Token::from_bytes(&bytes)?;
+                TokenSigner::key_id(&token)?;
+                Ok(AnyToken::SignedToken(token))
+            }
+            _ => Err(CsrfError::MalformedEnvelope),
+        }
+    }
+}
+impl<'a> From<&'a Token> for Envelope {
+    fn from(token: &'a Token) -> Envelope {
+        Envelope::new(TokenKind::Token, None, token.to_bytes())
+    }
+}
+impl<'a> From<&'a PaddedToken> for Envelope {
+    fn from(token: &'a PaddedToken) -> Envelope {
+        Envelope::new(TokenKind::PaddedToken, None, token.to_bytes())
+    }
+}
+impl<'a> From<&'a TimedToken> for Envelope {
+    fn from(token: &'a TimedToken) -> Envelope {
+        Envelope::new(TokenKind::TimedToken, None, token.to_bytes())
+    }
+}
+impl<'a> From<&'a EncryptedToken> for Envelope {
+    fn from(token: &'a EncryptedToken) -> Envelope {
+        let bytes = token.to_bytes();
+        Envelope::new(TokenKind::EncryptedToken,
+                      Some(token.key_id()),
+                      bytes[KEY_ID_LEN..].to_vec())
+    }
+}
+impl fmt::Display for Envelope {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", base64::encode(&self.to_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::{AnyToken, CsrfError, EncryptedToken, Envelope, ExpiryPolicy, Keyring, PaddedToken,
+                Token, TokenCipher, TokenKind, TokenSigner};
+
+    fn roundtrip(envelope: Envelope) -> AnyToken {
+        let decoded = Envelope::from_base64_str(&envelope.to_string()).unwrap();
+        assert_eq!(decoded, envelope);
+        decoded.open().unwrap()
+    }
+
+    #[test]
+    fn tokens_roundtrip_through_envelope() {
+        let token = Token::new();
+        let padded_token = PaddedToken::new(&token);
+        match roundtrip(Envelope::from(&token)) {
+            AnyToken::Token(decoded) => assert!(decoded == token),
+            other => panic!("unexpected token {:?}", other),
+        }
+        match roundtrip(Envelope::from(&padded_token)) {
+            AnyToken::PaddedToken(decoded) => assert!(decoded == padded_token),
+            other => panic!("unexpected token {:?}", other),
+        }
+        let timed_token = ExpiryPolicy::new(Duration::from_secs(60)).issue(Token::new());
+        match roundtrip(Envelope::from(&timed_token)) {
+            AnyToken::TimedToken(decoded) => assert!(decoded.token() == timed_token.token()),
+            other => panic!("unexpected token {:?}", other),
+        }
+    }
+    #[test]
+    fn encrypted_token_carries_key_id() {
+        let cipher = TokenCipher::new(&[7; 32], Duration::from_secs(60));
+        let token = cipher.seal(b"session");
+        let envelope = Envelope::from(&token);
+        assert_eq!(envelope.key_id(), Some(0));
+        assert_eq!(envelope.kind(), TokenKind::EncryptedToken);
+        match roundtrip(envelope) {
+            AnyToken::EncryptedToken(decoded) => {
+                assert!(cipher.verify(&decoded, b"session").is_ok())
+            }
+            other => panic!("unexpected token {:?}", other),
+        }
+    }
+    #[test]
+    fn signed_token_carries_key_id() {
+        let signer = TokenSigner::with_keyring(Keyring::new(7, b"secret".to_vec()));
+        let token = signer.sign(Some(b"session"));
+        let envelope = Envelope::signed(&token).unwrap();
+        assert_eq!(envelope.key_id(), Some(7));
+        assert_eq!(envelope.kind(), TokenKind::SignedToken);
+        match roundtrip(envelope) {
+            AnyToken::SignedToken(decoded) => {
+                assert!(signer.verify(&decoded, Some(b"session")).is_ok())
+            }
+            other => panic!("unexpected token {:?}", other),
+        }
+        assert!(matches!(Envelope::signed(&Token::from_bytes(&[0; 16]).unwrap()),
+                         Err(CsrfError::InvalidLength(16))));
+        assert!(matches!(Envelope::new(TokenKind::SignedToken, Some(7), vec![0; 12]).open(),
+                         Err(CsrfError::InvalidLength(16))));
+        assert!(matches!(Envelope::new(TokenKind::SignedToken, None, vec![0; 32]).open(),
+                         Err(CsrfError::MalformedEnvelope)));
+    }
+    #[test]
+    fn byte_layout_matches_fixed_vector() {
+        let envelope = Envelope::new(TokenKind::EncryptedToken, Some(0x01020304), vec![9; 4]);
+        assert_eq!(envelope.to_bytes(), [1, 4, 1, 1, 2, 3, 4, 9, 9, 9, 9]);
+        let token = Token::from_bytes(&[1, 2, 3, 4]).unwrap();
+        assert_eq!(Envelope::from(&token).to_bytes(), [1, 1, 0, 1, 2, 3, 4]);
+        let bytes: Vec<u8> = (1..5).chain(0..40).collect();
+        let token = EncryptedToken::from_bytes(&bytes).unwrap();
+        assert_eq!(Envelope::from(&token).to_bytes()[..7], [1, 4, 1, 1, 2, 3, 4]);
+        assert_eq!(Envelope::from(&token).to_bytes()[7..], bytes[4..]);
+        let bytes: Vec<u8> = (1..33).collect();
+        let envelope = Envelope::signed(&Token::from_bytes(&bytes).unwrap()).unwrap();
+        assert_eq!(envelope.to_bytes()[..7], [1, 5, 1, 1, 2, 3, 4]);
+        assert_eq!(envelope.to_bytes()[7..], bytes[4..]);
+    }
+    #[test]
+    fn unknown_version_and_kind_are_rejected() {
+        let mut bytes = Envelope::from(&Token::new()).to_bytes();
+        bytes[1] = 9;
+        assert!(matches!(Envelope::from_bytes(&bytes), Err(CsrfError::UnknownTokenKind(9))));
+        bytes[0] = 2;
+        assert!(matches!(Envelope::from_bytes(&bytes), Err(CsrfError::UnsupportedVersion(2))));
+        assert!(matches!(Envelope::from_bytes(&[1, 1, 2]), Err(CsrfError::MalformedEnvelope)));
+        assert!(matches!(Envelope::new(TokenKind::Token, Some(1), vec![0; 32]).open(),
+                         Err(CsrfError::MalformedEnvelope)));
+    }
+}
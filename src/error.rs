@@ -0,0 +1,151 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Error type.
+
+use base64::Base64Error;
+use crate::TokenEncoding;
+use std::error::Error;
+use std::fmt;
+
+/// Errors that can happen when decoding or verifying tokens.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CsrfError {
+    /// The token is not valid base64.
+    InvalidBase64(Base64Error),
+    /// The token has missing or extra base64 padding.
+    InvalidPadding,
+    /// The token is validly encoded, but not in its canonical form, for example because of
+    /// unused bits that are not zero.
+    NonCanonicalEncoding,
+    /// The token contains characters or has a length not valid in the given encoding.
+    InvalidEncoding(TokenEncoding),
+    /// The decoded token has an invalid length in bytes.
+    InvalidLength(usize),
+    /// No token was submitted.
+    MissingToken,
+    /// The submitted token does not match the expected token.
+    TokenMismatch,
+    /// The token has expired.
+    Expired,
+    /// The token signature or authentication tag is invalid.
+    BadSignature,
+    /// A single-use token was submitted more than once.
+    AlreadyRedeemed,
+    /// A single-use token cannot be redeemed because the replay cache is full.
+    ReplayCacheFull,
+    /// No token is stored for the session.
+    UnknownSession,
+    /// The `TokenStore` failed.
+    Store(Box<dyn Error + Send + Sync>),
+    /// Neither an `Origin` nor a `Referer` header was present.
+    MissingOrigin,
+    /// The request does not originate from a trusted origin.
+    OriginMismatch,
+    /// A configured origin cannot be parsed.
+    InvalidOrigin(String),
+    /// The random number generator failed.
+    Rng(rand::Error),
+    /// The token was created with a key that is not in the keyring.
+    UnknownKey(u32),
+    /// A key with the same id is already in the keyring.
+    DuplicateKey(u32),
+    /// The active key cannot be retired.
+    ActiveKey(u32),
+    /// The token envelope has a version this library does not support.
+    UnsupportedVersion(u8),
+    /// The token envelope holds an unknown kind of token.
+    UnknownTokenKind(u8),
+    /// The token envelope is malformed.
+    MalformedEnvelope,
+    /// The request body is larger than the configured limit.
+    BodyTooLarge(usize),
+    /// The request body could not be read.
+    InvalidBody,
+    /// An element of a page being rewritten makes a form that already received a token submit
+    /// to another origin.
+    FormRetargeted,
+}
+impl fmt::Display for CsrfError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CsrfError::InvalidBase64(_) => write!(fmt, "token is not valid base64"),
+            CsrfError::InvalidPadding => write!(fmt, "token has invalid base64 padding"),
+            CsrfError::NonCanonicalEncoding => write!(fmt, "token is not in canonical encoding"),
+            CsrfError::InvalidEncoding(encoding) => {
+                write!(fmt, "token is not valid {:?} encoding", encoding)
+            }
+            CsrfError::InvalidLength(len) => write!(fmt, "invalid token length: {} bytes", len),
+            CsrfError::MissingToken => write!(fmt, "no token was submitted"),
+            CsrfError::TokenMismatch => write!(fmt, "token does not match"),
+            CsrfError::Expired => write!(fmt, "token has expired"),
+            CsrfError::BadSignature => write!(fmt, "token signature is invalid"),
+            CsrfError::AlreadyRedeemed => write!(fmt, "token has already been redeemed"),
+            CsrfError::ReplayCacheFull => write!(fmt, "replay cache is full"),
+            CsrfError::UnknownSession => write!(fmt, "no token is stored for the session"),
+            CsrfError::Store(_) => write!(fmt, "token store failed"),
+            CsrfError::MissingOrigin => write!(fmt, "request has no Origin or Referer header"),
+            CsrfError::OriginMismatch => write!(fmt, "request origin is not trusted"),
+            CsrfError::InvalidOrigin(ref origin) => write!(fmt, "invalid origin: {}", origin),
+            CsrfError::Rng(_) => write!(fmt, "random number generator failed"),
+            CsrfError::UnknownKey(id) => write!(fmt, "unknown key id: {}", id),
+            CsrfError::DuplicateKey(id) => write!(fmt, "duplicate key id: {}", id),
+            CsrfError::ActiveKey(id) => write!(fmt, "key {} is the active key", id),
+            CsrfError::UnsupportedVersion(version) => {
+                write!(fmt, "unsupported token version: {}", version)
+            }
+            CsrfError::UnknownTokenKind(kind) => write!(fmt, "unknown token kind: {}", kind),
+            CsrfError::MalformedEnvelope => write!(fmt, "malformed token envelope"),
+            CsrfError::BodyTooLarge(limit) => {
+                write!(fmt, "request body is larger than {} bytes", limit)
+            }
+            CsrfError::InvalidBody => write!(fmt, "request body could not be read"),
+            CsrfError::FormRetargeted => {
+                write!(fmt, "form with a token was made to submit to another origin")
+            }
+        }
+    }
+}
+impl Error for CsrfError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            CsrfError::InvalidBase64(ref e) => Some(e),
+            CsrfError::Rng(ref e) => Some(e),
+            CsrfError::Store(ref e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+impl From<Base64Error> for CsrfError {
+    fn from(e: Base64Error) -> CsrfError {
+        CsrfError::InvalidBase64(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use crate::{CsrfError, Token};
+
+    #[test]
+    fn invalid_base64_has_source() {
+        let e = Token::from_base64_str("not base64!").unwrap_err();
+        assert!(matches!(e, CsrfError::InvalidBase64(_)));
+        assert!(e.source().is_some());
+        assert_eq!(e.to_string(), "token is not valid base64");
+    }
+    #[test]
+    fn invalid_length_is_reported() {
+        let e = Token::from_base64_str("AQID").unwrap_err();
+        assert!(matches!(e, CsrfError::InvalidLength(3)));
+        assert!(e.source().is_none());
+        assert_eq!(e.to_string(), "invalid token length: 3 bytes");
+    }
+}
@@ -0,0 +1,232 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Token expiry.
+
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::decode::decode_base64;
+use crate::{CsrfError, DecodeMode, PaddedToken, Token};
+
+const TIMESTAMP_LEN: usize = 8;
+
+/// Source of the current time for expiry checks.
+pub trait Clock {
+    /// Returns the current time.
+    fn now(&self) -> SystemTime;
+}
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> SystemTime {
+        (**self).now()
+    }
+}
+
+/// `Clock` that uses the system time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Converts `time` to seconds since the UNIX epoch. Times before the epoch are clamped to it.
+pub(crate) fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// A `Token` together with the time it was issued. Meant to be stored in the server session in
+/// place of a bare `Token` when tokens should expire.
+#[derive(Debug)]
+pub struct TimedToken {
+    token: Token,
+    issued_at: u64,
+}
+impl TimedToken {
+    /// Returns the underlying `Token`.
+    pub fn token(&self) -> &Token {
+        &self.token
+    }
+    /// Returns the time the token was issued, with a precision of one second.
+    pub fn issued_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.issued_at)
+    }
+    /// Creates a new `TimedToken` from a base64 encoded string
+    pub fn from_base64_str(base64: &str) -> Result<TimedToken, CsrfError> {
+        TimedToken::from_bytes(&decode_base64(base64, DecodeMode::Strict)?)
+    }
+    /// Creates a new `TimedToken` from its byte representation, the issue time as big-endian
+    /// seconds since the UNIX epoch followed by the token.
+    pub fn from_bytes(bytes: &[u8]) -> Result<TimedToken, CsrfError> {
+        if bytes.len() < TIMESTAMP_LEN {
+            return Err(CsrfError::InvalidLength(bytes.len()));
+        }
+        let (timestamp, token) = bytes.split_at(TIMESTAMP_LEN);
+        let token = Token::from_bytes(token)?;
+        let mut issued_at = [0u8; TIMESTAMP_LEN];
+        issued_at.copy_from_slice(timestamp);
+        Ok(TimedToken {
+            token,
+            issued_at: u64::from_be_bytes(issued_at),
+        })
+    }
+    /// Returns the byte representation of the `TimedToken`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.issued_at.to_be_bytes().to_vec();
+        bytes.extend_from_slice(self.token.as_ref());
+        bytes
+    }
+}
+impl fmt::Display for TimedToken {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", base64::encode(&self.to_bytes()))
+    }
+}
+
+/// Issues `TimedToken`s and rejects tokens older than a configured time to live.
+///
+/// To tolerate clock differences between servers, tokens issued up to `skew` in the future are
+/// accepted and tokens are valid for `skew` past their time to live.
+pub struct ExpiryPolicy<C = SystemClock> {
+    ttl: Duration,
+    skew: Duration,
+    clock: C,
+}
+impl ExpiryPolicy<SystemClock> {
+    /// Creates a new `ExpiryPolicy` using the system time.
+    pub fn new(ttl: Duration) -> ExpiryPolicy<SystemClock> {
+        ExpiryPolicy::with_clock(ttl, SystemClock)
+    }
+}
+impl<C: Clock> ExpiryPolicy<C> {
+    /// Creates a new `ExpiryPolicy` that gets the current time from `clock`.
+    pub fn with_clock(ttl: Duration, clock: C) -> ExpiryPolicy<C> {
+        ExpiryPolicy {
+            ttl,
+            skew: Duration::from_secs(0),
+            clock,
+        }
+    }
+    /// Sets the tolerated clock skew.
+    pub fn with_skew(mut self, skew: Duration) -> ExpiryPolicy<C> {
+        self.skew = skew;
+        self
+    }
+    /// Creates a new `TimedToken` issued at the current time.
+    pub fn issue(&self, token: Token) -> TimedToken {
+        TimedToken {
+            token,
+            issued_at: self.now(),
+        }
+    }
+    /// Checks that `token` has not expired.
+    pub fn is_fresh(&self, token: &TimedToken) -> bool {
+        self.is_fresh_at(token.issued_at)
+    }
+    /// Checks that `token` has not expired and that `padded_token` unmasks to it.
+    pub fn verify(&self, token: &TimedToken, padded_token: &PaddedToken) -> Result<(), CsrfError> {
+        if !self.is_fresh(token) {
+            return Err(CsrfError::Expired);
+        }
+        if padded_token.unmask() != token.token {
+            return Err(CsrfError::TokenMismatch);
+        }
+        Ok(())
+    }
+    pub(crate) fn now(&self) -> u64 {
+        unix_secs(self.clock.now())
+    }
+    pub(crate) fn expires_at(&self, issued_at: u64) -> u64 {
+        issued_at.saturating_add(self.ttl.as_secs()).saturating_add(self.skew.as_secs())
+    }
+    pub(crate) fn is_fresh_at(&self, issued_at: u64) -> bool {
+        let now = self.now();
+        let skew = self.skew.as_secs();
+        issued_at <= now.saturating_add(skew) &&
+        now <= self.expires_at(issued_at)
+    }
+}
+
+/// `Clock` that is set and advanced by hand, shared by the tests of time-dependent tokens.
+#[cfg(test)]
+pub(crate) struct ManualClock(std::cell::Cell<u64>);
+#[cfg(test)]
+impl ManualClock {
+    /// Creates a new `ManualClock` at `secs` seconds since the UNIX epoch.
+    pub(crate) fn new(secs: u64) -> ManualClock {
+        ManualClock(std::cell::Cell::new(secs))
+    }
+    /// Sets the time to `secs` seconds since the UNIX epoch.
+    pub(crate) fn set(&self, secs: u64) {
+        self.0.set(secs);
+    }
+    /// Advances the time by `secs` seconds.
+    pub(crate) fn advance(&self, secs: u64) {
+        self.0.set(self.0.get() + secs);
+    }
+}
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.0.get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+    use super::ManualClock;
+    use crate::{CsrfError, ExpiryPolicy, PaddedToken, TimedToken, Token};
+
+    #[test]
+    fn token_expires_after_ttl() {
+        let clock = ManualClock::new(1000);
+        let policy = ExpiryPolicy::with_clock(Duration::from_secs(60), &clock);
+        let token = policy.issue(Token::new());
+        let padded_token = PaddedToken::new(token.token());
+        clock.advance(60);
+        assert!(policy.verify(&token, &padded_token).is_ok());
+        assert!(matches!(policy.verify(&token, &PaddedToken::new(&Token::new())),
+                         Err(CsrfError::TokenMismatch)));
+        clock.advance(1);
+        assert!(matches!(policy.verify(&token, &padded_token), Err(CsrfError::Expired)));
+    }
+    #[test]
+    fn clock_skew_is_tolerated() {
+        let clock = ManualClock::new(1000);
+        let issuer = ExpiryPolicy::with_clock(Duration::from_secs(60), ManualClock::new(1010));
+        let token = issuer.issue(Token::new());
+        let strict = ExpiryPolicy::with_clock(Duration::from_secs(60), &clock);
+        let lenient = ExpiryPolicy::with_clock(Duration::from_secs(60), &clock)
+            .with_skew(Duration::from_secs(10));
+        assert!(!strict.is_fresh(&token));
+        assert!(lenient.is_fresh(&token));
+        clock.advance(80);
+        assert!(lenient.is_fresh(&token));
+        clock.advance(1);
+        assert!(!lenient.is_fresh(&token));
+    }
+    #[test]
+    fn byte_layout_matches_fixed_vector() {
+        let bytes: Vec<u8> = (1..9).chain(0..16).collect();
+        let token = TimedToken::from_bytes(&bytes).unwrap();
+        assert_eq!(token.issued_at(), UNIX_EPOCH + Duration::from_secs(0x0102030405060708));
+        assert_eq!(token.token().as_ref(), &bytes[8..]);
+        assert_eq!(token.to_bytes(), bytes);
+        assert_eq!(token.to_string(), "AQIDBAUGBwgAAQIDBAUGBwgJCgsMDQ4P");
+    }
+    #[test]
+    fn base64_encode_and_decode_timed_token() {
+        let policy = ExpiryPolicy::new(Duration::from_secs(60));
+        let token = policy.issue(Token::new());
+        let decoded = TimedToken::from_base64_str(&token.to_string()).unwrap();
+        assert!(decoded.token() == token.token());
+        assert_eq!(decoded.issued_at(), token.issued_at());
+    }
+}
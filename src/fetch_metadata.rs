@@ -0,0 +1,207 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Fetch Metadata resource isolation policy.
+
+use crate::{is_safe_method, PaddedToken, Token};
+
+/// Why a request was allowed by a `FetchMetadataPolicy`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AllowReason {
+    /// The request has a safe method.
+    SafeMethod,
+    /// `Sec-Fetch-Site` is `same-origin`.
+    SameOrigin,
+    /// `Sec-Fetch-Site` is `same-site` and same-site requests are allowed.
+    SameSite,
+    /// `Sec-Fetch-Site` is `none`, meaning the user initiated the request.
+    UserInitiated,
+    /// The request is a cross-site top-level navigation with a safe method.
+    Navigation,
+    /// Fetch Metadata headers were absent and the submitted token was valid.
+    TokenVerified,
+}
+
+/// Why a request was rejected by a `FetchMetadataPolicy`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RejectReason {
+    /// `Sec-Fetch-Site` is `cross-site`.
+    CrossSite,
+    /// `Sec-Fetch-Site` is `same-site` and same-site requests are not allowed.
+    SameSite,
+    /// `Sec-Fetch-Site` has an unknown value. Such requests are treated as cross-site.
+    UnknownSite(String),
+    /// Fetch Metadata headers were absent and no token was submitted.
+    MissingToken,
+    /// Fetch Metadata headers were absent and the submitted token was invalid.
+    InvalidToken,
+}
+
+/// Result of evaluating a request against a `FetchMetadataPolicy`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FetchMetadataDecision {
+    /// The request is allowed.
+    Allowed(AllowReason),
+    /// The request is rejected.
+    Rejected(RejectReason),
+}
+impl FetchMetadataDecision {
+    /// Returns `true` if the request is allowed.
+    pub fn is_allowed(&self) -> bool {
+        match *self {
+            FetchMetadataDecision::Allowed(_) => true,
+            FetchMetadataDecision::Rejected(_) => false,
+        }
+    }
+}
+
+/// Policy using the `Sec-Fetch-Site` and `Sec-Fetch-Mode` headers sent by modern browsers to
+/// reject cross-site requests with unsafe methods. Requests from browsers that do not send these
+/// headers are verified with a `PaddedToken` instead.
+///
+/// By default same-site requests are allowed and cross-site requests with safe methods are
+/// allowed. With resource isolation enabled, cross-site requests with safe methods are allowed
+/// only for top-level navigations.
+#[derive(Debug)]
+pub struct FetchMetadataPolicy {
+    allow_same_site: bool,
+    isolate_resources: bool,
+}
+impl FetchMetadataPolicy {
+    /// Creates a new `FetchMetadataPolicy` with the default settings.
+    pub fn new() -> FetchMetadataPolicy {
+        FetchMetadataPolicy {
+            allow_same_site: true,
+            isolate_resources: false,
+        }
+    }
+    /// Sets whether requests from other origins of the same site are allowed.
+    pub fn allow_same_site(mut self, allow: bool) -> FetchMetadataPolicy {
+        self.allow_same_site = allow;
+        self
+    }
+    /// Sets whether cross-site requests with safe methods are restricted to top-level
+    /// navigations.
+    pub fn isolate_resources(mut self, isolate: bool) -> FetchMetadataPolicy {
+        self.isolate_resources = isolate;
+        self
+    }
+    /// Evaluates a request given its method and the values of its `Sec-Fetch-Site` and
+    /// `Sec-Fetch-Mode` headers. When `Sec-Fetch-Site` is absent, requests with unsafe methods
+    /// are allowed only if `submitted` unmasks to `token`.
+    pub fn evaluate(&self,
+                    method: &str,
+                    site: Option<&str>,
+                    mode: Option<&str>,
+                    token: &Token,
+                    submitted: Option<&PaddedToken>)
+                    -> FetchMetadataDecision {
+        use self::AllowReason::*;
+        use self::FetchMetadataDecision::*;
+        use self::RejectReason::*;
+
+        let site = match site {
+            Some(site) => site.trim().to_ascii_lowercase(),
+            None if is_safe_method(method) => return Allowed(SafeMethod),
+            None => {
+                return match submitted {
+                    Some(submitted) if submitted.unmask() == *token => Allowed(TokenVerified),
+                    Some(_) => Rejected(InvalidToken),
+                    None => Rejected(MissingToken),
+                };
+            }
+        };
+        let cross_site_reason = match &site[..] {
+            "same-origin" => return Allowed(SameOrigin),
+            "none" => return Allowed(UserInitiated),
+            "same-site" if self.allow_same_site => return Allowed(AllowReason::SameSite),
+            "same-site" => RejectReason::SameSite,
+            "cross-site" => CrossSite,
+            _ => UnknownSite(site),
+        };
+        if !is_safe_method(method) {
+            Rejected(cross_site_reason)
+        } else if !self.isolate_resources {
+            Allowed(SafeMethod)
+        } else if mode.is_some_and(|mode| mode.trim().eq_ignore_ascii_case("navigate")) &&
+                  (method.eq_ignore_ascii_case("GET") || method.eq_ignore_ascii_case("HEAD")) {
+            Allowed(Navigation)
+        } else {
+            Rejected(cross_site_reason)
+        }
+    }
+}
+impl Default for FetchMetadataPolicy {
+    fn default() -> FetchMetadataPolicy {
+        FetchMetadataPolicy::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AllowReason::*;
+    use super::FetchMetadataDecision::*;
+    use super::RejectReason::*;
+    use crate::{FetchMetadataPolicy, PaddedToken, Token};
+
+    #[test]
+    fn trusted_sites_are_allowed() {
+        let policy = FetchMetadataPolicy::new();
+        let token = Token::new();
+        assert_eq!(policy.evaluate("POST", Some("same-origin"), Some("cors"), &token, None),
+                   Allowed(SameOrigin));
+        assert_eq!(policy.evaluate("POST", Some("none"), Some("navigate"), &token, None),
+                   Allowed(UserInitiated));
+        assert_eq!(policy.evaluate("POST", Some("same-site"), Some("cors"), &token, None),
+                   Allowed(super::AllowReason::SameSite));
+        let policy = policy.allow_same_site(false);
+        assert_eq!(policy.evaluate("POST", Some("same-site"), Some("cors"), &token, None),
+                   Rejected(super::RejectReason::SameSite));
+    }
+    #[test]
+    fn cross_site_unsafe_requests_are_rejected() {
+        let policy = FetchMetadataPolicy::new();
+        let token = Token::new();
+        let padded_token = PaddedToken::new(&token);
+        assert_eq!(policy.evaluate("POST",
+                                   Some("cross-site"),
+                                   Some("navigate"),
+                                   &token,
+                                   Some(&padded_token)),
+                   Rejected(CrossSite));
+        assert_eq!(policy.evaluate("GET", Some("cross-site"), Some("no-cors"), &token, None),
+                   Allowed(SafeMethod));
+        assert_eq!(policy.evaluate("POST", Some("bogus"), None, &token, None),
+                   Rejected(UnknownSite("bogus".into())));
+        assert_eq!(policy.evaluate("GET", Some("bogus"), None, &token, None),
+                   Allowed(SafeMethod));
+    }
+    #[test]
+    fn resource_isolation_allows_only_navigations() {
+        let policy = FetchMetadataPolicy::new().isolate_resources(true);
+        let token = Token::new();
+        assert_eq!(policy.evaluate("GET", Some("cross-site"), Some("navigate"), &token, None),
+                   Allowed(Navigation));
+        assert_eq!(policy.evaluate("GET", Some("cross-site"), Some("no-cors"), &token, None),
+                   Rejected(CrossSite));
+    }
+    #[test]
+    fn token_is_verified_without_headers() {
+        let policy = FetchMetadataPolicy::new();
+        let token = Token::new();
+        let padded_token = PaddedToken::new(&token);
+        let other = PaddedToken::new(&Token::new());
+        assert_eq!(policy.evaluate("GET", None, None, &token, None), Allowed(SafeMethod));
+        assert_eq!(policy.evaluate("POST", None, None, &token, Some(&padded_token)),
+                   Allowed(TokenVerified));
+        assert_eq!(policy.evaluate("POST", None, None, &token, Some(&other)),
+                   Rejected(InvalidToken));
+        assert_eq!(policy.evaluate("POST", None, None, &token, None), Rejected(MissingToken));
+    }
+}
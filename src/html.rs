@@ -0,0 +1,140 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! HTML rendering helpers.
+
+use std::fmt::{self, Write};
+use crate::{CsrfConfig, PaddedToken, TokenEncoding};
+
+/// Writes to a formatter, escaping characters that are special in HTML text and attributes.
+struct EscapingWriter<'a, 'b>(&'a mut fmt::Formatter<'b>);
+impl Write for EscapingWriter<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut rest = s;
+        while let Some(i) = rest.find(['&', '<', '>', '"', '\'']) {
+            self.0.write_str(&rest[..i])?;
+            self.0.write_str(match rest.as_bytes()[i] {
+                b'&' => "&amp;",
+                b'<' => "&lt;",
+                b'>' => "&gt;",
+                b'"' => "&quot;",
+                _ => "&#39;",
+            })?;
+            rest = &rest[i + 1..];
+        }
+        self.0.write_str(rest)
+    }
+}
+
+/// `Display` wrapper that HTML escapes the wrapped value, so that it can be embedded in element
+/// content or a quoted attribute value.
+#[derive(Clone, Copy, Debug)]
+pub struct Escaped<T>(pub T);
+impl<T: fmt::Display> fmt::Display for Escaped<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(EscapingWriter(fmt), "{}", self.0)
+    }
+}
+
+/// Token rendered by `HiddenInput`, either a `PaddedToken` to encode or an encoded one.
+#[derive(Clone, Copy, Debug)]
+enum TokenValue<'a> {
+    Padded(&'a PaddedToken, TokenEncoding),
+    Encoded(&'a str),
+}
+impl fmt::Display for TokenValue<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TokenValue::Padded(token, encoding) => write!(fmt, "{}", token.encode(encoding)),
+            TokenValue::Encoded(token) => fmt.write_str(token),
+        }
+    }
+}
+
+/// Hidden form input carrying a `PaddedToken` in the configured form field. Created with
+/// `CsrfConfig::hidden_input` or `CsrfConfig::encoded_hidden_input`.
+#[derive(Clone, Copy, Debug)]
+pub struct HiddenInput<'a> {
+    field_name: &'a str,
+    value: TokenValue<'a>,
+}
+impl fmt::Display for HiddenInput<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt,
+               r#"<input type="hidden" name="{}" value="{}">"#,
+               Escaped(self.field_name),
+               Escaped(self.value))
+    }
+}
+
+/// `<meta name="csrf-token">` tag carrying a `PaddedToken` for scripts to send in the configured
+/// header. Created with `CsrfConfig::meta_tag`.
+#[derive(Clone, Copy, Debug)]
+pub struct MetaTag<'a> {
+    token: &'a PaddedToken,
+    encoding: TokenEncoding,
+}
+impl fmt::Display for MetaTag<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt,
+               r#"<meta name="csrf-token" content="{}">"#,
+               Escaped(self.token.encode(self.encoding)))
+    }
+}
+
+impl CsrfConfig {
+    /// Returns a hidden form input carrying `token` in the configured form field.
+    pub fn hidden_input<'a>(&'a self, token: &'a PaddedToken) -> HiddenInput<'a> {
+        HiddenInput {
+            field_name: self.field_name(),
+            value: TokenValue::Padded(token, self.encoding()),
+        }
+    }
+    /// Returns a hidden form input carrying an already encoded `PaddedToken`, such as the
+    /// `CsrfToken` of the request displayed in its own encoding, in the configured form field.
+    pub fn encoded_hidden_input<'a>(&'a self, token: &'a str) -> HiddenInput<'a> {
+        HiddenInput {
+            field_name: self.field_name(),
+            value: TokenValue::Encoded(token),
+        }
+    }
+    /// Returns a `<meta name="csrf-token">` tag carrying `token`.
+    pub fn meta_tag<'a>(&self, token: &'a PaddedToken) -> MetaTag<'a> {
+        MetaTag {
+            token,
+            encoding: self.encoding(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CsrfConfig, Escaped, PaddedToken, Token, TokenEncoding};
+
+    #[test]
+    fn escapes_special_characters() {
+        assert_eq!(Escaped(r#"<a href="x?a=1&b='2'">"#).to_string(),
+                   "&lt;a href=&quot;x?a=1&amp;b=&#39;2&#39;&quot;&gt;");
+        assert_eq!(Escaped("plain").to_string(), "plain");
+    }
+    #[test]
+    fn renders_hidden_input_with_configured_field() {
+        let token = PaddedToken::new(&Token::new());
+        let config = CsrfConfig::new().with_field_name(r#"a"b"#);
+        let value = token.encode(TokenEncoding::Base64Url);
+        assert_eq!(config.hidden_input(&token).to_string(),
+                   format!(r#"<input type="hidden" name="a&quot;b" value="{}">"#, value));
+        assert_eq!(config.encoded_hidden_input("a<b").to_string(),
+                   r#"<input type="hidden" name="a&quot;b" value="a&lt;b">"#);
+        let config = config.with_encoding(TokenEncoding::Hex);
+        assert_eq!(config.meta_tag(&token).to_string(),
+                   format!(r#"<meta name="csrf-token" content="{}">"#,
+                           token.encode(TokenEncoding::Hex)));
+    }
+}
@@ -0,0 +1,128 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Key management and rotation.
+
+use crate::CsrfError;
+
+/// Identifier of a key in a `Keyring`. Signed and encrypted tokens carry the id of the key they
+/// were created with.
+pub type KeyId = u32;
+
+/// Versioned server keys used by `TokenSigner` and `TokenCipher`.
+///
+/// New tokens are created with the active key, and tokens created with any key still in the
+/// keyring are accepted. To rotate keys without invalidating issued tokens, add the new key,
+/// promote it once every server knows it, and retire the old key after the longest token
+/// lifetime has passed.
+#[derive(Clone, Debug)]
+pub struct Keyring<K> {
+    keys: Vec<(KeyId, K)>,
+    active: KeyId,
+}
+impl<K> Keyring<K> {
+    /// Creates a new `Keyring` with `key` as the active key.
+    pub fn new(id: KeyId, key: K) -> Keyring<K> {
+        Keyring {
+            keys: vec![(id, key)],
+            active: id,
+        }
+    }
+    /// Adds a key that is accepted for verification but not used for new tokens until promoted.
+    pub fn add(&mut self, id: KeyId, key: K) -> Result<(), CsrfError> {
+        if self.get(id).is_some() {
+            return Err(CsrfError::DuplicateKey(id));
+        }
+        self.keys.push((id, key));
+        Ok(())
+    }
+    /// Makes the key `id` the active key used for new tokens.
+    pub fn promote(&mut self, id: KeyId) -> Result<(), CsrfError> {
+        self.get(id).ok_or(CsrfError::UnknownKey(id))?;
+        self.active = id;
+        Ok(())
+    }
+    /// Removes the key `id`, after which tokens created with it are rejected. The active key
+    /// cannot be retired.
+    pub fn retire(&mut self, id: KeyId) -> Result<(), CsrfError> {
+        if id == self.active {
+            return Err(CsrfError::ActiveKey(id));
+        }
+        let index = self.keys.iter().position(|&(key_id, _)| key_id == id);
+        self.keys.remove(index.ok_or(CsrfError::UnknownKey(id))?);
+        Ok(())
+    }
+    /// Returns the id of the active key.
+    pub fn active_id(&self) -> KeyId {
+        self.active
+    }
+    /// Returns the ids of all keys accepted for verification.
+    pub fn ids(&self) -> Vec<KeyId> {
+        self.keys.iter().map(|&(id, _)| id).collect()
+    }
+    pub(crate) fn active(&self) -> (KeyId, &K) {
+        (self.active, self.get(self.active).expect("active key is in the keyring"))
+    }
+    pub(crate) fn get(&self, id: KeyId) -> Option<&K> {
+        self.keys.iter().find(|&&(key_id, _)| key_id == id).map(|(_, key)| key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::{CsrfError, ExpiryPolicy, Keyring, PaddedToken, TokenCipher, TokenSigner};
+
+    #[test]
+    fn add_promote_and_retire() {
+        let mut keyring = Keyring::new(1, b"first".to_vec());
+        assert!(matches!(keyring.add(1, b"again".to_vec()), Err(CsrfError::DuplicateKey(1))));
+        keyring.add(2, b"second".to_vec()).unwrap();
+        assert_eq!(keyring.active_id(), 1);
+        assert!(matches!(keyring.promote(3), Err(CsrfError::UnknownKey(3))));
+        keyring.promote(2).unwrap();
+        assert_eq!(keyring.active_id(), 2);
+        assert!(matches!(keyring.retire(2), Err(CsrfError::ActiveKey(2))));
+        keyring.retire(1).unwrap();
+        assert!(matches!(keyring.retire(1), Err(CsrfError::UnknownKey(1))));
+        assert_eq!(keyring.ids(), [2]);
+    }
+    #[test]
+    fn signed_tokens_straddle_rotation() {
+        let mut signer = TokenSigner::with_keyring(Keyring::new(1, b"first".to_vec()));
+        let old = PaddedToken::new(&signer.sign(Some(b"session")));
+
+        signer.keyring_mut().add(2, b"second".to_vec()).unwrap();
+        signer.keyring_mut().promote(2).unwrap();
+        let new = PaddedToken::new(&signer.sign(Some(b"session")));
+        assert!(signer.verify(&old.unmask(), Some(b"session")).is_ok());
+        assert!(signer.verify(&new.unmask(), Some(b"session")).is_ok());
+
+        signer.keyring_mut().retire(1).unwrap();
+        assert!(matches!(signer.verify(&old.unmask(), Some(b"session")),
+                         Err(CsrfError::UnknownKey(1))));
+        assert!(signer.verify(&new.unmask(), Some(b"session")).is_ok());
+    }
+    #[test]
+    fn encrypted_tokens_straddle_rotation() {
+        let expiry = ExpiryPolicy::new(Duration::from_secs(60));
+        let mut cipher = TokenCipher::with_keyring(Keyring::new(1, [1; 32]), expiry);
+        let old = cipher.seal(b"session");
+
+        cipher.keyring_mut().add(2, [2; 32]).unwrap();
+        cipher.keyring_mut().promote(2).unwrap();
+        let new = cipher.seal(b"session");
+        assert!(cipher.verify(&old, b"session").is_ok());
+        assert!(cipher.verify(&new, b"session").is_ok());
+
+        cipher.keyring_mut().retire(1).unwrap();
+        assert!(matches!(cipher.verify(&old, b"session"), Err(CsrfError::UnknownKey(1))));
+        assert!(cipher.verify(&new, b"session").is_ok());
+    }
+}
@@ -10,131 +10,420 @@
 //! CSRF token library inspired by golang's [gorilla/csrf](https://github.com/gorilla/csrf).
 
 extern crate base64;
-extern crate byteorder;
+extern crate chacha20poly1305;
 extern crate constant_time_eq;
+extern crate hmac;
 extern crate rand;
+#[cfg(any(test, feature = "test-rng"))]
+extern crate rand_chacha;
+extern crate sha2;
+
+mod config;
+mod decode;
+mod double_submit;
+mod encoding;
+mod encrypted;
+mod envelope;
+mod error;
+mod expiry;
+mod fetch_metadata;
+mod html;
+mod keyring;
+mod one_time;
+mod origin;
+mod request;
+#[cfg(feature = "serde")]
+mod serialize;
+mod signed;
+mod store;
+
+#[cfg(feature = "actix-web")]
+pub mod actix;
+#[cfg(feature = "askama")]
+pub mod askama;
+#[cfg(feature = "axum")]
+pub mod axum;
+#[cfg(feature = "body")]
+pub mod body;
+#[cfg(feature = "rewrite")]
+pub mod rewrite;
+#[cfg(feature = "rocket")]
+pub mod rocket;
+#[cfg(feature = "tera")]
+pub mod tera;
+#[cfg(feature = "tower")]
+pub mod tower;
+
+pub use config::{CsrfConfig, SameSite};
+pub use decode::DecodeMode;
+pub use double_submit::DoubleSubmitCookie;
+pub use encoding::TokenEncoding;
+pub use encrypted::{EncryptedToken, TokenCipher};
+pub use envelope::{AnyToken, Envelope, TokenKind};
+pub use error::CsrfError;
+pub use expiry::{Clock, ExpiryPolicy, SystemClock, TimedToken};
+pub use fetch_metadata::{AllowReason, FetchMetadataDecision, FetchMetadataPolicy, RejectReason};
+pub use html::{Escaped, HiddenInput, MetaTag};
+pub use keyring::{KeyId, Keyring};
+pub use one_time::{LruReplayCache, OneTimeTokens, ReplayCache};
+pub use origin::{is_safe_method, OriginVerifier};
+pub use request::CsrfToken;
+pub use signed::TokenSigner;
+pub use store::{InMemoryTokenStore, SynchronizerToken, TokenStore};
+
+/// Deterministic random number generator for reproducible tests. Never use it in production.
+#[cfg(feature = "test-rng")]
+pub use rand_chacha::ChaCha20Rng as SeededRng;
 
-use byteorder::{LittleEndian, ReadBytesExt};
 use constant_time_eq::constant_time_eq;
-use rand::Rng;
-use std::error::Error;
+use rand::rngs::OsRng;
+use rand::{CryptoRng, RngCore};
 use std::fmt;
-use std::io::Cursor;
-use std::mem;
-use std::slice;
 
-/// Error type for wrapping errors that can happen during base64 decoding.
-#[derive(Debug)]
-pub struct Base64DecodeError(pub String);
+/// Length in bytes of the 32-bit tokens produced by earlier versions of this crate.
+/// These are still accepted when decoding so that existing sessions keep working.
+const LEGACY_TOKEN_LEN: usize = 4;
+
+/// Former error type for base64 decoding errors.
+#[deprecated(note = "use `CsrfError` instead")]
+pub type Base64DecodeError = CsrfError;
+
+/// Amount of entropy in a newly created `Token`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TokenLength {
+    /// 128-bit token.
+    Bits128,
+    /// 256-bit token.
+    #[default]
+    Bits256,
+}
+impl TokenLength {
+    /// Returns the length of the token in bytes.
+    pub fn bytes(&self) -> usize {
+        match *self {
+            TokenLength::Bits128 => 16,
+            TokenLength::Bits256 => 32,
+        }
+    }
+}
+
+fn is_valid_token_len(len: usize) -> bool {
+    len == LEGACY_TOKEN_LEN || len == TokenLength::Bits128.bytes() ||
+    len == TokenLength::Bits256.bytes()
+}
 
-/// Actual token that `PaddedToken`s are compared against. Meant to be stored in the server session.
-#[derive(Debug, Default)]
-pub struct Token(u32);
+/// Actual token that `PaddedToken`s are compared against. Meant to be stored in the server session,
+/// unless it was created by a `TokenSigner`.
+///
+/// The byte representation of a `Token` is its secret bytes in order, independent of the host's
+/// byte order. For 32-bit tokens created by earlier versions these are the little-endian bytes of
+/// the former `u32`.
+#[derive(Clone, Debug)]
+pub struct Token(Vec<u8>);
 impl Token {
-    /// Creates a new `Token` using operating system's random number generator.
+    /// Creates a new 256-bit `Token` using operating system's random number generator.
+    /// Panics if the random number generator fails.
     pub fn new() -> Token {
-        let mut rng = rand::os::OsRng::new().unwrap();
-        Token(rng.next_u32())
+        Token::try_new().unwrap()
     }
-    /// Creates a new `Token` from a base64 encoded string
-    pub fn from_base64_str(base64: &str) -> Result<Token, Base64DecodeError> {
-        let mut bytes = Cursor::new(try!(base64::decode(base64)
-            .map_err(|e| Base64DecodeError(e.description().into()))));
-        let token = try!(bytes.read_u32::<LittleEndian>()
-            .map_err(|e| Base64DecodeError(e.description().into())));
-        Ok(Token(token))
+    /// Creates a new 256-bit `Token` using operating system's random number generator.
+    pub fn try_new() -> Result<Token, CsrfError> {
+        Token::try_with_length(TokenLength::default())
     }
-}
-impl<'a> Into<&'a [u8]> for &'a Token {
-    fn into(self) -> &'a [u8] {
-        unsafe {
-            slice::from_raw_parts(&self.0 as *const u32 as *const u8,
-                                  mem::size_of_val(&self.0))
+    /// Creates a new `Token` of the given length using operating system's random number generator.
+    /// Panics if the random number generator fails.
+    pub fn with_length(length: TokenLength) -> Token {
+        Token::try_with_length(length).unwrap()
+    }
+    /// Creates a new `Token` of the given length using operating system's random number generator.
+    pub fn try_with_length(length: TokenLength) -> Result<Token, CsrfError> {
+        Token::with_rng(length, &mut OsRng)
+    }
+    /// Creates a new `Token` of the given length using `rng`.
+    pub fn with_rng<R: RngCore + CryptoRng>(length: TokenLength,
+                                            rng: &mut R)
+                                            -> Result<Token, CsrfError> {
+        let mut bytes = vec![0u8; length.bytes()];
+        rng.try_fill_bytes(&mut bytes).map_err(CsrfError::Rng)?;
+        Ok(Token(bytes))
+    }
+    /// Creates a new `Token` from a base64 encoded string.
+    /// Accepts 128- and 256-bit tokens as well as 32-bit tokens created by earlier versions.
+    pub fn from_base64_str(base64: &str) -> Result<Token, CsrfError> {
+        Token::from_base64_str_with(base64, DecodeMode::Strict)
+    }
+    /// Creates a new `Token` from a base64 encoded string using the given `DecodeMode`.
+    pub fn from_base64_str_with(base64: &str, mode: DecodeMode) -> Result<Token, CsrfError> {
+        Token::from_bytes(&TokenEncoding::Base64.decode_with(base64, mode)?)
+    }
+    /// Creates a new `Token` from a string in the given encoding.
+    pub fn decode(encoded: &str, encoding: TokenEncoding) -> Result<Token, CsrfError> {
+        Token::from_bytes(&encoding.decode(encoded)?)
+    }
+    /// Returns the `Token` in the given encoding.
+    pub fn encode(&self, encoding: TokenEncoding) -> String {
+        encoding.encode(self.as_ref())
+    }
+    /// Creates a new `Token` from its byte representation.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Token, CsrfError> {
+        if !is_valid_token_len(bytes.len()) {
+            return Err(CsrfError::InvalidLength(bytes.len()));
         }
+        Ok(Token(bytes.to_vec()))
+    }
+    /// Returns the byte representation of the `Token`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.clone()
+    }
+}
+impl Default for Token {
+    fn default() -> Token {
+        Token::new()
+    }
+}
+impl AsRef<[u8]> for Token {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+impl<'a> From<&'a Token> for &'a [u8] {
+    fn from(token: &'a Token) -> &'a [u8] {
+        token.as_ref()
     }
 }
 impl fmt::Display for Token {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        write!(fmt, "{}", base64::encode(self.into()))
+        write!(fmt, "{}", self.encode(TokenEncoding::Base64))
     }
 }
 impl PartialEq for Token {
     fn eq(&self, rhs: &Token) -> bool {
-        constant_time_eq(self.into(), rhs.into())
+        constant_time_eq(self.as_ref(), rhs.as_ref())
     }
 }
 
 /// A token that can be used in HTML forms.
 /// A compromise is made between security and convenience in a way that every
 /// token is different, but all the data needed for decoding the real token is present.
-/// `PaddedToken` internally is a 32-bit one-time token concatenated with the real `Token` that is
-/// XOR'd with the one-time token.
-#[derive(Debug)]
-pub struct PaddedToken(u64);
+/// `PaddedToken` internally is a one-time pad of the same length as the real `Token`,
+/// concatenated with the real `Token` that is XOR'd with the pad.
+///
+/// The byte representation of a `PaddedToken` is the pad followed by the masked token. Padded
+/// 32-bit tokens created by earlier versions hold the little-endian bytes of the masked token
+/// followed by those of the pad.
+#[derive(Clone, Debug)]
+pub struct PaddedToken(Vec<u8>);
 impl PaddedToken {
     /// Creates a new `PaddedToken` using operating system's random number generator.
+    /// Panics if the random number generator fails.
     pub fn new(real_token: &Token) -> PaddedToken {
-        let mut rng = rand::os::OsRng::new().unwrap();
-        let otp = rng.next_u32();
-        let masked = otp ^ real_token.0;
-        PaddedToken(((otp as u64) << 32) | masked as u64)
+        PaddedToken::try_new(real_token).unwrap()
+    }
+    /// Creates a new `PaddedToken` using operating system's random number generator.
+    pub fn try_new(real_token: &Token) -> Result<PaddedToken, CsrfError> {
+        PaddedToken::with_rng(real_token, &mut OsRng)
+    }
+    /// Creates a new `PaddedToken` using `rng`.
+    pub fn with_rng<R: RngCore + CryptoRng>(real_token: &Token,
+                                            rng: &mut R)
+                                            -> Result<PaddedToken, CsrfError> {
+        let mut bytes = vec![0u8; real_token.0.len() * 2];
+        {
+            let (otp, masked) = bytes.split_at_mut(real_token.0.len());
+            rng.try_fill_bytes(otp).map_err(CsrfError::Rng)?;
+            for ((m, o), t) in masked.iter_mut().zip(otp.iter()).zip(real_token.0.iter()) {
+                *m = o ^ t;
+            }
+        }
+        Ok(PaddedToken(bytes))
     }
     /// Unmasks a `PaddedToken` and returning the underlying `Token`.
     pub fn unmask(&self) -> Token {
-        let otp: u32 = (self.0 >> 32) as u32;
-        let masked: u32 = (self.0 & 0xFFFFFFFF) as u32;
-        Token(otp ^ masked)
-    }
-    /// Creates a new `PaddedToken` from a base64 encoded string
-    pub fn from_base64_str(base64: &str) -> Result<PaddedToken, Base64DecodeError> {
-        let mut bytes = Cursor::new(try!(base64::decode(base64)
-            .map_err(|e| Base64DecodeError(e.description().into()))));
-        let token = try!(bytes.read_u64::<LittleEndian>()
-            .map_err(|e| Base64DecodeError(e.description().into())));
-        Ok(PaddedToken(token))
+        // XOR is symmetric, so this works regardless of which half holds the pad. Tokens from
+        // earlier versions store the masked token first.
+        let (otp, masked) = self.0.split_at(self.0.len() / 2);
+        Token(otp.iter().zip(masked).map(|(o, m)| o ^ m).collect())
     }
-}
-impl<'a> Into<&'a [u8]> for &'a PaddedToken {
-    fn into(self) -> &'a [u8] {
-        unsafe {
-            slice::from_raw_parts(&self.0 as *const u64 as *const u8,
-                                  mem::size_of_val(&self.0))
+    /// Creates a new `PaddedToken` from a base64 encoded string.
+    /// Accepts padded 128- and 256-bit tokens as well as padded 32-bit tokens created by earlier
+    /// versions.
+    pub fn from_base64_str(base64: &str) -> Result<PaddedToken, CsrfError> {
+        PaddedToken::from_base64_str_with(base64, DecodeMode::Strict)
+    }
+    /// Creates a new `PaddedToken` from a base64 encoded string using the given `DecodeMode`.
+    pub fn from_base64_str_with(base64: &str, mode: DecodeMode) -> Result<PaddedToken, CsrfError> {
+        PaddedToken::from_bytes(&TokenEncoding::Base64.decode_with(base64, mode)?)
+    }
+    /// Creates a new `PaddedToken` from a string in the given encoding.
+    pub fn decode(encoded: &str, encoding: TokenEncoding) -> Result<PaddedToken, CsrfError> {
+        PaddedToken::from_bytes(&encoding.decode(encoded)?)
+    }
+    /// Returns the `PaddedToken` in the given encoding.
+    pub fn encode(&self, encoding: TokenEncoding) -> String {
+        encoding.encode(self.as_ref())
+    }
+    /// Creates a new `PaddedToken` from its byte representation.
+    pub fn from_bytes(bytes: &[u8]) -> Result<PaddedToken, CsrfError> {
+        if !bytes.len().is_multiple_of(2) || !is_valid_token_len(bytes.len() / 2) {
+            return Err(CsrfError::InvalidLength(bytes.len()));
         }
+        Ok(PaddedToken(bytes.to_vec()))
+    }
+    /// Returns the byte representation of the `PaddedToken`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.clone()
+    }
+}
+impl AsRef<[u8]> for PaddedToken {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+impl<'a> From<&'a PaddedToken> for &'a [u8] {
+    fn from(token: &'a PaddedToken) -> &'a [u8] {
+        token.as_ref()
     }
 }
 impl fmt::Display for PaddedToken {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        write!(fmt, "{}", base64::encode(self.into()))
+        write!(fmt, "{}", self.encode(TokenEncoding::Base64))
     }
 }
 impl PartialEq for PaddedToken {
     fn eq(&self, rhs: &PaddedToken) -> bool {
-        constant_time_eq(self.into(), rhs.into())
+        constant_time_eq(self.as_ref(), rhs.as_ref())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    /// Random number generator that always fails, for testing error paths.
+    struct FailingRng;
+    impl rand::RngCore for FailingRng {
+        fn next_u32(&mut self) -> u32 {
+            self.next_u64() as u32
+        }
+        fn next_u64(&mut self) -> u64 {
+            let mut bytes = [0u8; 8];
+            self.fill_bytes(&mut bytes);
+            u64::from_le_bytes(bytes)
+        }
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            self.try_fill_bytes(dest).unwrap_or_else(|e| panic!("{e}"))
+        }
+        fn try_fill_bytes(&mut self, _: &mut [u8]) -> Result<(), rand::Error> {
+            Err(rand::Error::new(std::io::Error::other("no entropy")))
+        }
+    }
+    impl rand::CryptoRng for FailingRng {}
+
     #[test]
     fn masking_and_unmasking_produces_same_token() {
-        let token = ::Token::new();
-        let padded_token = ::PaddedToken::new(&token);
+        let token = crate::Token::new();
+        let padded_token = crate::PaddedToken::new(&token);
         let unmasked = padded_token.unmask();
         assert!(unmasked == token);
     }
     #[test]
     fn base64_encode_and_decode_token() {
-        let token = ::Token::new();
+        let token = crate::Token::new();
         let base64 = format!("{}", token);
-        let base64_decoded = ::Token::from_base64_str(&base64).ok().unwrap();
+        let base64_decoded = crate::Token::from_base64_str(&base64).ok().unwrap();
         assert!(token == base64_decoded);
     }
     #[test]
     fn base64_encode_and_decode_paddedtoken() {
-        let token = ::Token::new();
-        let padded_token = ::PaddedToken::new(&token);
+        let token = crate::Token::new();
+        let padded_token = crate::PaddedToken::new(&token);
         let base64 = format!("{}", padded_token);
-        let base64_decoded = ::PaddedToken::from_base64_str(&base64).ok().unwrap();
+        let base64_decoded = crate::PaddedToken::from_base64_str(&base64).ok().unwrap();
         assert!(padded_token == base64_decoded);
     }
+    #[test]
+    fn token_lengths() {
+        assert_eq!(crate::Token::new().0.len(), 32);
+        let token = crate::Token::with_length(crate::TokenLength::Bits128);
+        assert_eq!(token.0.len(), 16);
+        let padded_token = crate::PaddedToken::new(&token);
+        assert_eq!(padded_token.0.len(), 32);
+        assert!(padded_token.unmask() == token);
+    }
+    #[test]
+    fn legacy_32_bit_tokens_decode() {
+        let token = crate::Token::from_base64_str("AQIDBA==").unwrap();
+        let padded_token = crate::PaddedToken::from_base64_str("3M64rt3Mu6o=").unwrap();
+        assert!(padded_token.unmask() == token);
+    }
+    #[test]
+    fn byte_representation_is_independent_of_host() {
+        let token = crate::Token::from_bytes(&[1, 2, 3, 4]).unwrap();
+        assert_eq!(token.to_string(), "AQIDBA==");
+        assert_eq!(token.to_bytes(), [1, 2, 3, 4]);
+        let padded_bytes = [0xDC, 0xCE, 0xB8, 0xAE, 0xDD, 0xCC, 0xBB, 0xAA];
+        let padded_token = crate::PaddedToken::from_bytes(&padded_bytes).unwrap();
+        assert_eq!(padded_token.to_string(), "3M64rt3Mu6o=");
+        assert_eq!(padded_token.unmask().as_ref(), token.as_ref());
+        let bytes: Vec<u8> = (0..32).collect();
+        let token = crate::Token::from_bytes(&bytes).unwrap();
+        assert_eq!(token.to_string(), "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=");
+        assert!(crate::Token::from_base64_str(&token.to_string()).unwrap() == token);
+    }
+    #[test]
+    fn seeded_rng_is_reproducible() {
+        use rand_chacha::rand_core::SeedableRng;
+        use rand_chacha::ChaCha20Rng;
+        let mut rng = ChaCha20Rng::from_seed([1; 32]);
+        let token = crate::Token::with_rng(crate::TokenLength::Bits128, &mut rng).unwrap();
+        let padded_token = crate::PaddedToken::with_rng(&token, &mut rng).unwrap();
+        let mut rng = ChaCha20Rng::from_seed([1; 32]);
+        assert!(crate::Token::with_rng(crate::TokenLength::Bits128, &mut rng).unwrap() == token);
+        assert!(crate::PaddedToken::with_rng(&token, &mut rng).unwrap() == padded_token);
+    }
+    #[test]
+    fn rng_failure_is_reported() {
+        use std::time::Duration;
+        use crate::{CsrfError, DoubleSubmitCookie, InMemoryTokenStore, LruReplayCache,
+                    OneTimeTokens, SynchronizerToken, TokenCipher, TokenSigner};
+
+        assert!(matches!(crate::Token::with_rng(crate::TokenLength::Bits256, &mut FailingRng),
+                         Err(CsrfError::Rng(_))));
+        assert!(matches!(crate::PaddedToken::with_rng(&crate::Token::new(), &mut FailingRng),
+                         Err(CsrfError::Rng(_))));
+        assert!(matches!(TokenSigner::new(b"secret").sign_with_rng(None, &mut FailingRng),
+                         Err(CsrfError::Rng(_))));
+        let cipher = TokenCipher::new(&[7; 32], Duration::from_secs(60));
+        assert!(matches!(cipher.seal_with_rng(b"session", &mut FailingRng),
+                         Err(CsrfError::Rng(_))));
+        let tokens = OneTimeTokens::new(b"secret", Duration::from_secs(60), LruReplayCache::new(8));
+        assert!(matches!(tokens.issue_with_rng(b"session", &mut FailingRng),
+                         Err(CsrfError::Rng(_))));
+        assert!(matches!(DoubleSubmitCookie::new().issue_with_rng(None, &mut FailingRng),
+                         Err(CsrfError::Rng(_))));
+        let synchronizer = SynchronizerToken::new(InMemoryTokenStore::new(Duration::from_secs(60)));
+        assert!(matches!(synchronizer.issue_with_rng(b"session", &mut FailingRng),
+                         Err(CsrfError::Rng(_))));
+    }
+    #[test]
+    fn lenient_decoding_ignores_whitespace() {
+        let token = crate::Token::new();
+        let padded_token = crate::PaddedToken::new(&token);
+        let base64 = format!(" {}\n", padded_token);
+        assert!(crate::PaddedToken::from_base64_str(&base64).is_err());
+        let decoded = crate::PaddedToken::from_base64_str_with(&base64, crate::DecodeMode::Lenient)
+            .unwrap();
+        assert!(decoded == padded_token);
+    }
+    #[test]
+    fn trailing_data_is_rejected() {
+        let token = crate::Token::new();
+        let mut bytes = token.to_bytes();
+        bytes.push(0);
+        assert!(matches!(crate::Token::from_base64_str(&base64::encode(&bytes)),
+                         Err(crate::CsrfError::InvalidLength(33))));
+    }
+    #[test]
+    fn invalid_token_length_is_rejected() {
+        assert!(crate::Token::from_base64_str("AQID").is_err());
+        assert!(crate::PaddedToken::from_base64_str("AQIDBA==").is_err());
+    }
 }
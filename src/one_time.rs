@@ -0,0 +1,229 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Single-use tokens.
+
+use crate::expiry::SystemClock;
+use crate::signed::NONCE_DATA_LEN;
+use rand::rngs::OsRng;
+use rand::{CryptoRng, RngCore};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::{Clock, CsrfError, ExpiryPolicy, PaddedToken, TokenSigner};
+
+const TIMESTAMP_LEN: usize = 8;
+
+/// Record of redeemed single-use token ids.
+pub trait ReplayCache {
+    /// Records `id` as redeemed until `expires_at`. Fails with `CsrfError::AlreadyRedeemed` if
+    /// `id` had already been redeemed and the record has not expired by `now`, and with
+    /// `CsrfError::ReplayCacheFull` if `id` cannot be recorded.
+    fn redeem(&self, id: &[u8], now: SystemTime, expires_at: SystemTime) -> Result<(), CsrfError>;
+}
+
+/// In-memory `ReplayCache` holding at most `capacity` ids.
+///
+/// Ids are dropped once they expire. Ids that have not expired are never dropped, as a token
+/// with a dropped id could be redeemed again, so redemptions fail while the cache is full.
+/// `capacity` should cover the number of tokens redeemed within the token time to live.
+pub struct LruReplayCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+struct Entries {
+    expiries: HashMap<Vec<u8>, SystemTime>,
+    order: VecDeque<Vec<u8>>,
+}
+impl LruReplayCache {
+    /// Creates a new `LruReplayCache` holding at most `capacity` ids.
+    pub fn new(capacity: usize) -> LruReplayCache {
+        LruReplayCache {
+            capacity,
+            entries: Mutex::new(Entries {
+                expiries: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+        }
+    }
+    /// Returns the number of recorded ids.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().expiries.len()
+    }
+    /// Returns `true` if no ids are recorded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+impl ReplayCache for LruReplayCache {
+    fn redeem(&self, id: &[u8], now: SystemTime, expires_at: SystemTime) -> Result<(), CsrfError> {
+        let mut entries = self.entries.lock().unwrap();
+        let Entries { ref mut expiries, ref mut order } = *entries;
+        while let Some(oldest) = order.pop_front() {
+            match expiries.get(&oldest) {
+                Some(&expiry) if expiry >= now => {
+                    order.push_front(oldest);
+                    break;
+                }
+                Some(_) => {
+                    expiries.remove(&oldest);
+                }
+                None => {}
+            }
+        }
+        match expiries.get(id) {
+            Some(&expiry) if expiry >= now => return Err(CsrfError::AlreadyRedeemed),
+            Some(_) => {
+                expiries.remove(id);
+                order.retain(|redeemed| &redeemed[..] != id);
+            }
+            None => {}
+        }
+        if expiries.len() >= self.capacity {
+            // Ids are ordered by redemption, not expiry, so expired ids can follow live ones.
+            expiries.retain(|_, &mut expiry| expiry >= now);
+            order.retain(|redeemed| expiries.contains_key(redeemed));
+            if expiries.len() >= self.capacity {
+                return Err(CsrfError::ReplayCacheFull);
+            }
+        }
+        expiries.insert(id.to_vec(), expires_at);
+        order.push_back(id.to_vec());
+        Ok(())
+    }
+}
+
+/// Issues `PaddedToken`s that can be redeemed only once, for high-value actions.
+///
+/// Each token is signed and bound to a session, and its signed nonce holds the issue time. The
+/// token itself serves as a unique id, which is recorded in a `ReplayCache` on redemption until
+/// the token expires.
+pub struct OneTimeTokens<R, C = SystemClock> {
+    signer: TokenSigner,
+    expiry: ExpiryPolicy<C>,
+    cache: R,
+}
+impl<R: ReplayCache> OneTimeTokens<R, SystemClock> {
+    /// Creates a new `OneTimeTokens` signing tokens with `key`. Tokens expire after `ttl`.
+    pub fn new(key: &[u8], ttl: Duration, cache: R) -> OneTimeTokens<R, SystemClock> {
+        OneTimeTokens::with_expiry(key, ExpiryPolicy::new(ttl), cache)
+    }
+}
+impl<R: ReplayCache, C: Clock> OneTimeTokens<R, C> {
+    /// Creates a new `OneTimeTokens` signing tokens with `key` and using the given `ExpiryPolicy`.
+    pub fn with_expiry(key: &[u8], expiry: ExpiryPolicy<C>, cache: R) -> OneTimeTokens<R, C> {
+        OneTimeTokens::with_signer(TokenSigner::new(key), expiry, cache)
+    }
+    /// Creates a new `OneTimeTokens` signing tokens with `signer` and using the given
+    /// `ExpiryPolicy`.
+    pub fn with_signer(signer: TokenSigner,
+                       expiry: ExpiryPolicy<C>,
+                       cache: R)
+                       -> OneTimeTokens<R, C> {
+        OneTimeTokens {
+            signer,
+            expiry,
+            cache,
+        }
+    }
+    /// Returns the `TokenSigner` for rotating keys.
+    pub fn signer_mut(&mut self) -> &mut TokenSigner {
+        &mut self.signer
+    }
+    /// Creates a new single-use `PaddedToken` bound to `session_id`.
+    /// Panics if the random number generator fails.
+    pub fn issue(&self, session_id: &[u8]) -> PaddedToken {
+        self.try_issue(session_id).unwrap()
+    }
+    /// Creates a new single-use `PaddedToken` bound to `session_id` using operating system's
+    /// random number generator.
+    pub fn try_issue(&self, session_id: &[u8]) -> Result<PaddedToken, CsrfError> {
+        self.issue_with_rng(session_id, &mut OsRng)
+    }
+    /// Creates a new single-use `PaddedToken` bound to `session_id` using `rng`.
+    pub fn issue_with_rng<G: RngCore + CryptoRng>(&self,
+                                                  session_id: &[u8],
+                                                  rng: &mut G)
+                                                  -> Result<PaddedToken, CsrfError> {
+        let mut data = [0u8; NONCE_DATA_LEN];
+        data[..TIMESTAMP_LEN].copy_from_slice(&self.expiry.now().to_be_bytes());
+        rng.try_fill_bytes(&mut data[TIMESTAMP_LEN..]).map_err(CsrfError::Rng)?;
+        PaddedToken::with_rng(&self.signer.sign_nonce(&data, Some(session_id)), rng)
+    }
+    /// Verifies `padded_token` for `session_id` and marks it as used.
+    pub fn redeem(&self, session_id: &[u8], padded_token: &PaddedToken) -> Result<(), CsrfError> {
+        let token = padded_token.unmask();
+        self.signer.verify(&token, Some(session_id))?;
+        let mut issued_at = [0u8; TIMESTAMP_LEN];
+        issued_at.copy_from_slice(&TokenSigner::nonce_data(&token)[..TIMESTAMP_LEN]);
+        let issued_at = u64::from_be_bytes(issued_at);
+        if !self.expiry.is_fresh_at(issued_at) {
+            return Err(CsrfError::Expired);
+        }
+        let now = UNIX_EPOCH + Duration::from_secs(self.expiry.now());
+        let expires_at = UNIX_EPOCH + Duration::from_secs(self.expiry.expires_at(issued_at));
+        self.cache.redeem(token.as_ref(), now, expires_at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+    use crate::expiry::ManualClock;
+    use crate::{CsrfError, ExpiryPolicy, LruReplayCache, OneTimeTokens, PaddedToken, ReplayCache};
+
+    #[test]
+    fn token_can_be_redeemed_once() {
+        let cache = LruReplayCache::new(16);
+        let tokens = OneTimeTokens::new(b"secret", Duration::from_secs(60), cache);
+        let padded_token = tokens.issue(b"session");
+        let remasked = PaddedToken::new(&padded_token.unmask());
+        assert!(matches!(tokens.redeem(b"other", &padded_token), Err(CsrfError::BadSignature)));
+        assert!(tokens.redeem(b"session", &padded_token).is_ok());
+        assert!(matches!(tokens.redeem(b"session", &padded_token),
+                         Err(CsrfError::AlreadyRedeemed)));
+        assert!(matches!(tokens.redeem(b"session", &remasked), Err(CsrfError::AlreadyRedeemed)));
+        assert!(tokens.redeem(b"session", &tokens.issue(b"session")).is_ok());
+    }
+    #[test]
+    fn expired_token_is_rejected() {
+        let clock = ManualClock::new(1000);
+        let expiry = ExpiryPolicy::with_clock(Duration::from_secs(60), &clock);
+        let tokens = OneTimeTokens::with_expiry(b"secret", expiry, LruReplayCache::new(16));
+        let padded_token = tokens.issue(b"session");
+        clock.set(1061);
+        assert!(matches!(tokens.redeem(b"session", &padded_token), Err(CsrfError::Expired)));
+    }
+    #[test]
+    fn lru_cache_is_bounded_and_drops_expired_ids() {
+        let cache = LruReplayCache::new(2);
+        let now = UNIX_EPOCH + Duration::from_secs(1000);
+        let expires_at = now + Duration::from_secs(60);
+        assert!(cache.redeem(b"a", now, expires_at).is_ok());
+        assert!(cache.redeem(b"b", now, expires_at + Duration::from_secs(30)).is_ok());
+        assert!(matches!(cache.redeem(b"a", now, expires_at), Err(CsrfError::AlreadyRedeemed)));
+        assert!(matches!(cache.redeem(b"c", now, expires_at), Err(CsrfError::ReplayCacheFull)));
+        assert_eq!(cache.len(), 2);
+        let later = expires_at + Duration::from_secs(1);
+        assert!(cache.redeem(b"c", later, later + Duration::from_secs(60)).is_ok());
+        assert_eq!(cache.len(), 2);
+        assert!(cache.redeem(b"a", later, later + Duration::from_secs(60)).is_err());
+        assert!(matches!(cache.redeem(b"b", later, later), Err(CsrfError::AlreadyRedeemed)));
+    }
+    #[test]
+    fn full_cache_does_not_allow_replay() {
+        let tokens = OneTimeTokens::new(b"secret", Duration::from_secs(60), LruReplayCache::new(2));
+        let victim = tokens.issue(b"victim");
+        assert!(tokens.redeem(b"victim", &victim).is_ok());
+        assert!(tokens.redeem(b"attacker", &tokens.issue(b"attacker")).is_ok());
+        assert!(matches!(tokens.redeem(b"attacker", &tokens.issue(b"attacker")),
+                         Err(CsrfError::ReplayCacheFull)));
+        assert!(matches!(tokens.redeem(b"victim", &victim), Err(CsrfError::AlreadyRedeemed)));
+    }
+}
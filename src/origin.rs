@@ -0,0 +1,180 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Origin and Referer header verification.
+
+use crate::CsrfError;
+
+/// Returns `true` for HTTP methods that must not change state and thus need no CSRF protection.
+pub fn is_safe_method(method: &str) -> bool {
+    ["GET", "HEAD", "OPTIONS", "TRACE"].iter().any(|safe| method.eq_ignore_ascii_case(safe))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Origin {
+    scheme: String,
+    host: String,
+    port: u16,
+}
+impl Origin {
+    /// Parses the origin of an URL or a serialized origin such as `https://example.com:8443`.
+    /// Scheme and host are lowercased and a missing port is replaced with the scheme's default.
+    pub(crate) fn parse(url: &str) -> Option<Origin> {
+        let scheme_end = url.find("://")?;
+        let scheme = url[..scheme_end].to_ascii_lowercase();
+        let rest = &url[scheme_end + 3..];
+        let authority = &rest[..rest.find(['/', '?', '#']).unwrap_or(rest.len())];
+        let host_port = match authority.rfind('@') {
+            Some(at) => &authority[at + 1..],
+            None => authority,
+        };
+        let port_start = if host_port.starts_with('[') {
+            host_port.find(']').map(|end| end + 1)?
+        } else {
+            host_port.find(':').unwrap_or(host_port.len())
+        };
+        let (host, port) = host_port.split_at(port_start);
+        let port = match port {
+            "" => {
+                match &scheme[..] {
+                    "http" => 80,
+                    "https" => 443,
+                    _ => return None,
+                }
+            }
+            port if port.starts_with(':') => port[1..].parse().ok()?,
+            _ => return None,
+        };
+        if host.is_empty() || scheme.is_empty() {
+            return None;
+        }
+        Some(Origin {
+            scheme,
+            host: host.to_ascii_lowercase(),
+            port,
+        })
+    }
+}
+#[derive(Debug)]
+struct TrustedOrigin {
+    origin: Origin,
+    any_subdomain: bool,
+}
+impl TrustedOrigin {
+    fn matches(&self, origin: &Origin) -> bool {
+        if origin.scheme != self.origin.scheme || origin.port != self.origin.port {
+            return false;
+        }
+        if self.any_subdomain {
+            origin.host.len() > self.origin.host.len() + 1 &&
+            origin.host.ends_with(&self.origin.host) &&
+            origin.host[..origin.host.len() - self.origin.host.len()].ends_with('.')
+        } else {
+            origin.host == self.origin.host
+        }
+    }
+}
+
+/// Verifies that requests with unsafe methods originate from a trusted origin, using the
+/// `Origin` header and falling back to the `Referer` header when `Origin` is absent.
+///
+/// Requests are rejected when neither header is present, so this can be used on its own or in
+/// addition to token verification.
+#[derive(Debug, Default)]
+pub struct OriginVerifier {
+    trusted: Vec<TrustedOrigin>,
+}
+impl OriginVerifier {
+    /// Creates a new `OriginVerifier` that trusts no origins.
+    pub fn new() -> OriginVerifier {
+        OriginVerifier::default()
+    }
+    /// Adds a trusted origin such as `https://example.com`. Default ports are normalised, so
+    /// `https://example.com:443` is the same origin. A host of the form `*.example.com` trusts
+    /// every subdomain of `example.com`, but not `example.com` itself.
+    pub fn trust(mut self, origin: &str) -> Result<OriginVerifier, CsrfError> {
+        let any_subdomain = origin.contains("://*.");
+        let parsed = Origin::parse(&origin.replacen("://*.", "://", 1))
+            .ok_or_else(|| CsrfError::InvalidOrigin(origin.to_owned()))?;
+        self.trusted.push(TrustedOrigin {
+            origin: parsed,
+            any_subdomain,
+        });
+        Ok(self)
+    }
+    /// Verifies a request given its method and the values of its `Origin` and `Referer` headers.
+    /// Requests with safe methods are always accepted.
+    pub fn verify(&self,
+                  method: &str,
+                  origin: Option<&str>,
+                  referer: Option<&str>)
+                  -> Result<(), CsrfError> {
+        if is_safe_method(method) {
+            return Ok(());
+        }
+        let source = origin.or(referer).ok_or(CsrfError::MissingOrigin)?;
+        match Origin::parse(source.trim()) {
+            Some(ref source) if self.trusted.iter().any(|t| t.matches(source)) => Ok(()),
+            _ => Err(CsrfError::OriginMismatch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CsrfError, OriginVerifier};
+
+    fn verifier() -> OriginVerifier {
+        OriginVerifier::new()
+            .trust("https://example.com")
+            .and_then(|v| v.trust("http://*.example.org:8080"))
+            .unwrap()
+    }
+    fn post(origin: Option<&str>, referer: Option<&str>) -> Result<(), CsrfError> {
+        verifier().verify("POST", origin, referer)
+    }
+
+    #[test]
+    fn safe_methods_are_accepted() {
+        assert!(verifier().verify("GET", None, None).is_ok());
+        assert!(verifier().verify("head", Some("https://evil.com"), None).is_ok());
+    }
+    #[test]
+    fn origin_is_normalised() {
+        assert!(post(Some("https://example.com"), None).is_ok());
+        assert!(post(Some("HTTPS://Example.COM:443"), None).is_ok());
+        assert!(post(Some("http://example.com"), None).is_err());
+        assert!(post(Some("https://example.com:8443"), None).is_err());
+        assert!(post(Some("https://example.com.evil.com"), None).is_err());
+        assert!(matches!(post(Some("null"), Some("https://example.com/")),
+                         Err(CsrfError::OriginMismatch)));
+        assert!(matches!(post(None, None), Err(CsrfError::MissingOrigin)));
+    }
+    #[test]
+    fn wildcard_matches_subdomains_only() {
+        assert!(post(Some("http://a.example.org:8080"), None).is_ok());
+        assert!(post(Some("http://a.b.example.org:8080"), None).is_ok());
+        assert!(post(Some("http://example.org:8080"), None).is_err());
+        assert!(post(Some("http://aexample.org:8080"), None).is_err());
+        assert!(post(Some("http://a.example.org"), None).is_err());
+    }
+    #[test]
+    fn referer_is_used_without_origin() {
+        assert!(post(None, Some("https://user@example.com/form?a=b")).is_ok());
+        assert!(post(None, Some("https://evil.com/?https://example.com")).is_err());
+        assert!(post(Some("https://evil.com"), Some("https://example.com/")).is_err());
+    }
+    #[test]
+    fn invalid_trusted_origin_is_rejected() {
+        assert!(matches!(OriginVerifier::new().trust("example.com"),
+                         Err(CsrfError::InvalidOrigin(_))));
+        assert!(OriginVerifier::new().trust("ftp://example.com").is_err());
+        assert!(OriginVerifier::new().trust("https://example.com:port").is_err());
+    }
+}
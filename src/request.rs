@@ -0,0 +1,62 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Token of the current request, shared by the framework integrations.
+
+use std::fmt;
+use crate::{CsrfError, PaddedToken, Token, TokenEncoding};
+
+/// Token of the current request, made available to handlers by the framework middleware.
+/// Displays as a `PaddedToken` of the token to embed in forms or send in the configured header.
+#[derive(Clone, Debug)]
+pub struct CsrfToken {
+    padded_token: PaddedToken,
+    encoding: TokenEncoding,
+}
+impl CsrfToken {
+    /// Creates a new `CsrfToken` from the `Token` of the current request. Integrations for
+    /// other frameworks can use this to expose the token to handlers.
+    /// Panics if the random number generator fails.
+    pub fn new(token: &Token) -> CsrfToken {
+        CsrfToken::try_new(token).unwrap()
+    }
+    /// Creates a new `CsrfToken` from the `Token` of the current request using operating
+    /// system's random number generator.
+    pub fn try_new(token: &Token) -> Result<CsrfToken, CsrfError> {
+        Ok(CsrfToken {
+            padded_token: PaddedToken::try_new(token)?,
+            encoding: TokenEncoding::default(),
+        })
+    }
+    /// Sets the encoding the token is displayed in.
+    pub fn with_encoding(mut self, encoding: TokenEncoding) -> CsrfToken {
+        self.encoding = encoding;
+        self
+    }
+    /// Returns the `PaddedToken` of the current request.
+    pub fn padded_token(&self) -> &PaddedToken {
+        &self.padded_token
+    }
+    /// Returns the encoding the token is displayed in.
+    pub fn encoding(&self) -> TokenEncoding {
+        self.encoding
+    }
+    /// Verifies that `submitted` is a `PaddedToken` of the token of the current request.
+    pub fn verify(&self, submitted: &PaddedToken) -> Result<(), CsrfError> {
+        if submitted.unmask() != self.padded_token.unmask() {
+            return Err(CsrfError::TokenMismatch);
+        }
+        Ok(())
+    }
+}
+impl fmt::Display for CsrfToken {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.padded_token.encode(self.encoding))
+    }
+}
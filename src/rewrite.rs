@@ -0,0 +1,332 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Streaming HTML rewriter injecting hidden token inputs into forms.
+
+use lol_html::html_content::ContentType;
+use lol_html::{element, end_tag, HtmlRewriter, Settings};
+use std::cell::RefCell;
+use std::rc::Rc;
+use url::{Origin, Url};
+use crate::{CsrfConfig, CsrfError, PaddedToken, Token};
+
+pub use lol_html::errors::RewritingError;
+pub use lol_html::OutputSink;
+
+/// Rewrites an HTML document chunk by chunk, appending a hidden input with a fresh
+/// `PaddedToken` to every `<form method="post">` that submits to the origin of the page.
+///
+/// Actions are resolved like browsers do, against the first `<base href>` of the page. Forms
+/// whose action contains a named character reference other than the few that can form a URL
+/// are treated as submitting to another origin. Forms with a submit button whose `formaction`
+/// points to another origin get no input, including buttons associated with the form through
+/// their `form` attribute.
+///
+/// The first `<base href>` also applies to the forms before it, and buttons can be associated
+/// with forms before them. When such an element would make a form that already received an
+/// input submit to another origin, rewriting fails with `CsrfError::FormRetargeted` before the
+/// element is written, and the response must be aborted.
+///
+/// Only the current chunk is buffered, so pages can be rewritten while they are streamed.
+pub struct FormInjector<O: OutputSink> {
+    rewriter: HtmlRewriter<'static, O>,
+}
+impl<O: OutputSink> FormInjector<O> {
+    /// Creates a new `FormInjector` for a page served from `page_origin`, such as
+    /// `https://example.com`, that writes the rewritten document to `output`.
+    pub fn new(config: &CsrfConfig,
+               token: &Token,
+               page_origin: &str,
+               output: O)
+               -> Result<FormInjector<O>, CsrfError> {
+        let url = Url::parse(page_origin)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .ok_or_else(|| CsrfError::InvalidOrigin(page_origin.to_owned()))?;
+        let page = Rc::new(RefCell::new(Page {
+            origin: url.origin(),
+            base: Some(url.clone()),
+            url,
+            base_seen: false,
+            form: None,
+            injected: Vec::new(),
+            associated: Vec::new(),
+        }));
+        let base_urls = {
+            let page = page.clone();
+            element!("base[href]", move |el| {
+                let mut page = page.borrow_mut();
+                if page.base_seen {
+                    return Ok(());
+                }
+                page.base_seen = true;
+                let href = decode_references(&el.get_attribute("href").unwrap_or_default());
+                page.base = href.and_then(|href| page.url.join(&href).ok());
+                Ok(page.check_injected()?)
+            })
+        };
+        let submitters = {
+            let page = page.clone();
+            element!("button[formaction], input[formaction]", move |el| {
+                let url = el.get_attribute("formaction").unwrap_or_default();
+                let mut page = page.borrow_mut();
+                match el.get_attribute("form") {
+                    Some(id) => page.associated.push((id, url)),
+                    None => {
+                        if let Some(ref mut form) = page.form {
+                            form.urls.push(url);
+                        }
+                    }
+                }
+                Ok(page.check_injected()?)
+            })
+        };
+        let config = config.clone();
+        let token = token.clone();
+        let forms = element!("form", move |form| {
+            let is_post = form.get_attribute("method")
+                .map(|method| method.trim().eq_ignore_ascii_case("post"))
+                .unwrap_or(false);
+            if !is_post {
+                page.borrow_mut().form = None;
+                return Ok(());
+            }
+            page.borrow_mut().form = Some(Form {
+                id: form.get_attribute("id"),
+                urls: vec![form.get_attribute("action").unwrap_or_default()],
+            });
+            let (page, config, token) = (page.clone(), config.clone(), token.clone());
+            form.on_end_tag(end_tag!(move |end| {
+                let mut page = page.borrow_mut();
+                if let Some(form) = page.form.take().filter(|form| page.submits_to_origin(form)) {
+                    let padded_token = PaddedToken::try_new(&token)?;
+                    end.before(&config.hidden_input(&padded_token).to_string(), ContentType::Html);
+                    page.injected.push(form);
+                }
+                Ok(())
+            }))
+        });
+        let settings = Settings {
+            element_content_handlers: vec![base_urls, submitters, forms],
+            ..Settings::new()
+        };
+        Ok(FormInjector { rewriter: HtmlRewriter::new(settings, output) })
+    }
+    /// Rewrites the next chunk of the document.
+    pub fn write(&mut self, chunk: &[u8]) -> Result<(), RewritingError> {
+        self.rewriter.write(chunk)
+    }
+    /// Finishes rewriting the document.
+    pub fn end(self) -> Result<(), RewritingError> {
+        self.rewriter.end()
+    }
+}
+
+/// A `<form method="post">` and the URLs it submits to.
+struct Form {
+    id: Option<String>,
+    urls: Vec<String>,
+}
+
+/// State of the page being rewritten, shared by the element handlers.
+struct Page {
+    url: Url,
+    origin: Origin,
+    /// Base URL of the page, or `None` if its `<base href>` is not a valid URL.
+    base: Option<Url>,
+    base_seen: bool,
+    /// The `<form method="post">` being parsed.
+    form: Option<Form>,
+    /// Forms that received an input.
+    injected: Vec<Form>,
+    /// Ids of forms and the `formaction` of buttons associated with them by their `form`
+    /// attribute.
+    associated: Vec<(String, String)>,
+}
+impl Page {
+    /// Whether `url` refers to the origin of the page.
+    fn is_same_origin(&self, url: &str) -> bool {
+        let resolved = self.base.as_ref().and_then(|base| url_origin(base, url));
+        resolved.as_ref() == Some(&self.origin)
+    }
+    /// Whether `form` and the buttons associated with it submit only to the origin of the page.
+    fn submits_to_origin(&self, form: &Form) -> bool {
+        let associated = self.associated
+            .iter()
+            .filter(|&(id, _)| form.id.as_ref() == Some(id))
+            .map(|(_, url)| url);
+        form.urls.iter().chain(associated).all(|url| self.is_same_origin(url))
+    }
+    /// Fails if a form that received an input no longer submits only to the origin of the page.
+    fn check_injected(&self) -> Result<(), CsrfError> {
+        if !self.injected.iter().all(|form| self.submits_to_origin(form)) {
+            return Err(CsrfError::FormRetargeted);
+        }
+        Ok(())
+    }
+}
+
+/// Returns the origin of `url`, an attribute value on a page with the base URL `base`.
+fn url_origin(base: &Url, url: &str) -> Option<Origin> {
+    Some(base.join(&decode_references(url)?).ok()?.origin())
+}
+
+/// Decodes the character references in an attribute value up to the start of its query or
+/// fragment, as only that part can change the origin of a URL. Returns `None` for named
+/// references that are not decoded here.
+fn decode_references(value: &str) -> Option<String> {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(i) = rest.find(['&', '?', '#']) {
+        decoded.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if !rest.starts_with('&') {
+            break;
+        }
+        let (c, len) = char_reference(&rest[1..])?;
+        decoded.push(c);
+        rest = &rest[1 + len..];
+    }
+    decoded.push_str(rest);
+    Some(decoded)
+}
+
+/// Decodes the character reference following an `&`, returning the character and the length of
+/// the reference. An `&` that does not start a reference decodes to itself.
+fn char_reference(reference: &str) -> Option<(char, usize)> {
+    if let Some(number) = reference.strip_prefix('#') {
+        let (digits, radix, prefix) = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => (hex, 16, 2),
+            None => (number, 10, 1),
+        };
+        let len = digits.find(|c: char| !c.is_digit(radix)).unwrap_or(digits.len());
+        if len == 0 {
+            return Some(('&', 0));
+        }
+        let c = u32::from_str_radix(&digits[..len], radix)
+            .ok()
+            .and_then(char::from_u32)
+            .filter(|&c| c != '\0')
+            .unwrap_or(char::REPLACEMENT_CHARACTER);
+        return Some((c, prefix + len + usize::from(digits[len..].starts_with(';'))));
+    }
+    let len = reference.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(reference.len());
+    if len == 0 {
+        return Some(('&', 0));
+    }
+    if !reference[len..].starts_with(';') {
+        return None;
+    }
+    let c = match &reference[..len] {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "Tab" => '\t',
+        "NewLine" => '\n',
+        "sol" => '/',
+        "bsol" => '\\',
+        "colon" => ':',
+        "period" => '.',
+        "commat" => '@',
+        "quest" => '?',
+        "num" => '#',
+        _ => return None,
+    };
+    Some((c, len + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_references, FormInjector, RewritingError};
+    use crate::{CsrfConfig, CsrfError, PaddedToken, Token, TokenEncoding};
+
+    const PREFIX: &str = r#"<input type="hidden" name="csrf_token" value=""#;
+
+    /// Checks the injected inputs and replaces them with `{{csrf}}` placeholders.
+    fn check_inputs(mut html: &str, token: &Token) -> String {
+        let mut checked = String::new();
+        let mut seen = Vec::new();
+        while let Some(start) = html.find(PREFIX) {
+            let value = &html[start + PREFIX.len()..];
+            let end = value.find("\">").unwrap();
+            let padded_token = PaddedToken::decode(&value[..end], TokenEncoding::Base64Url);
+            assert!(padded_token.unwrap().unmask() == *token);
+            assert!(!seen.contains(&&value[..end]));
+            seen.push(&value[..end]);
+            checked.push_str(&html[..start]);
+            checked.push_str("{{csrf}}");
+            html = &value[end + 2..];
+        }
+        checked.push_str(html);
+        checked
+    }
+
+    /// Rewrites `html` in chunks of 7 bytes.
+    fn rewrite(html: &[u8], token: &Token) -> Result<String, RewritingError> {
+        let mut output = Vec::new();
+        let config = CsrfConfig::new();
+        let sink = |chunk: &[u8]| output.extend_from_slice(chunk);
+        let mut injector = FormInjector::new(&config, token, "https://example.com", sink).unwrap();
+        for chunk in html.chunks(7) {
+            injector.write(chunk)?;
+        }
+        injector.end()?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+    fn is_retargeted(error: RewritingError) -> bool {
+        match error {
+            RewritingError::ContentHandlerError(e) => {
+                matches!(e.downcast_ref(), Some(CsrfError::FormRetargeted))
+            }
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn injects_inputs_into_same_origin_post_forms() {
+        let token = Token::new();
+        let output = rewrite(include_bytes!("../tests/fixtures/forms.html"), &token).unwrap();
+        assert_eq!(check_inputs(&output, &token),
+                   include_str!("../tests/fixtures/forms.expected.html"));
+        let output = rewrite(include_bytes!("../tests/fixtures/base.html"), &token).unwrap();
+        assert_eq!(check_inputs(&output, &token),
+                   include_str!("../tests/fixtures/base.expected.html"));
+    }
+    #[test]
+    fn retargeting_forms_with_inputs_fails() {
+        let token = Token::new();
+        let form = r#"<form method="post" action="/a" id="f"></form>"#;
+        for late in [r#"<base href="https://other.example/">"#,
+                     r#"<button form="f" formaction="https://other.example/">"#] {
+            let html = format!("{}{}", form, late);
+            assert!(is_retargeted(rewrite(html.as_bytes(), &token).unwrap_err()));
+        }
+        for late in [r#"<base href="/b/">"#,
+                     r#"<button form="g" formaction="https://other.example/">"#] {
+            let html = format!("{}{}", form, late);
+            assert!(check_inputs(&rewrite(html.as_bytes(), &token).unwrap(), &token)
+                .contains("{{csrf}}"));
+        }
+    }
+    #[test]
+    fn character_references_are_decoded_before_query() {
+        assert_eq!(decode_references("/&#9;/&#x2F;a&sol;?b=1&c=2").unwrap(), "/\t//a/?b=1&c=2");
+        assert_eq!(decode_references("/a& &#1114112;&#;").unwrap(), "/a& \u{fffd}&#;");
+        assert_eq!(decode_references("/&unknown;"), None);
+        assert_eq!(decode_references("/a&b"), None);
+    }
+    #[test]
+    fn invalid_page_origin_is_rejected() {
+        let sink = |_: &[u8]| {};
+        let result = FormInjector::new(&CsrfConfig::new(), &Token::new(), "example.com", sink);
+        assert!(matches!(result, Err(CsrfError::InvalidOrigin(_))));
+    }
+}
@@ -0,0 +1,241 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Rocket fairing, request guards and form field type.
+//!
+//! Forms carry the token in a `SubmittedToken` field that handlers check with
+//! `SubmittedToken::verify`, which decodes it in the encoding of the fairing's configuration.
+//! Other non-idempotent routes can use the `VerifiedCsrf` guard, which checks the configured
+//! header.
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::form::{self, FromFormField, ValueField};
+use rocket::http::{Cookie, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Data, Request};
+use std::sync::Arc;
+use crate::{is_safe_method, CsrfConfig, CsrfError, PaddedToken, SameSite, Token, TokenEncoding};
+
+pub use crate::CsrfToken;
+
+/// Token and configuration of the current request, cached by the fairing for the guards. The
+/// token is missing when the fairing is not attached or could not create one.
+struct RequestState {
+    token: Option<Token>,
+    config: Arc<CsrfConfig>,
+}
+
+fn request_state<'r>(req: &'r Request<'_>) -> &'r RequestState {
+    req.local_cache(|| {
+        RequestState {
+            token: None,
+            config: Arc::new(CsrfConfig::new()),
+        }
+    })
+}
+
+/// `Fairing` that sets the token cookie when the client has none.
+pub struct CsrfFairing {
+    config: Arc<CsrfConfig>,
+}
+impl CsrfFairing {
+    /// Creates a new `CsrfFairing` using the names in `config`.
+    pub fn new(config: CsrfConfig) -> CsrfFairing {
+        CsrfFairing { config: Arc::new(config) }
+    }
+}
+impl Default for CsrfFairing {
+    fn default() -> CsrfFairing {
+        CsrfFairing::new(CsrfConfig::new())
+    }
+}
+#[rocket::async_trait]
+impl Fairing for CsrfFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "CSRF",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        let config = &self.config;
+        let cookie = req.cookies().get(config.cookie_name()).map(|c| c.value().to_owned());
+        let token = match cookie.map(|value| Token::decode(&value, config.encoding())) {
+            Some(Ok(token)) => Some(token),
+            _ => Token::try_new().ok().inspect(|token| {
+                let same_site = match config.same_site() {
+                    SameSite::Strict => rocket::http::SameSite::Strict,
+                    SameSite::Lax => rocket::http::SameSite::Lax,
+                    SameSite::None => rocket::http::SameSite::None,
+                };
+                let value = token.encode(config.encoding());
+                let cookie = Cookie::build((config.cookie_name().to_owned(), value));
+                req.cookies().add(cookie.path(config.cookie_path().to_owned())
+                    .http_only(true)
+                    .same_site(same_site)
+                    .secure(config.secure()));
+            }),
+        };
+        req.local_cache(|| {
+            RequestState {
+                token,
+                config: config.clone(),
+            }
+        });
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CsrfToken {
+    type Error = CsrfError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<CsrfToken, CsrfError> {
+        let state = request_state(req);
+        match state.token {
+            Some(ref token) => match CsrfToken::try_new(token) {
+                Ok(token) => Outcome::Success(token.with_encoding(state.config.encoding())),
+                Err(e) => Outcome::Error((Status::InternalServerError, e)),
+            },
+            None => Outcome::Error((Status::InternalServerError, CsrfError::MissingToken)),
+        }
+    }
+}
+
+/// Request guard that succeeds for safe requests and for unsafe requests carrying a
+/// `PaddedToken` of the cookie token in the configured header. Fails with
+/// `500 Internal Server Error` when the fairing has no token for the request.
+#[derive(Clone, Copy, Debug)]
+pub struct VerifiedCsrf;
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for VerifiedCsrf {
+    type Error = CsrfError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<VerifiedCsrf, CsrfError> {
+        match verify(req) {
+            Ok(()) => Outcome::Success(VerifiedCsrf),
+            Err(CsrfError::MissingToken) if request_state(req).token.is_none() => {
+                Outcome::Error((Status::InternalServerError, CsrfError::MissingToken))
+            }
+            Err(e) => Outcome::Error((Status::Forbidden, e)),
+        }
+    }
+}
+
+fn verify(req: &Request<'_>) -> Result<(), CsrfError> {
+    if is_safe_method(req.method().as_str()) {
+        return Ok(());
+    }
+    let state = request_state(req);
+    let token = state.token.as_ref().ok_or(CsrfError::MissingToken)?;
+    let submitted = req.headers()
+        .get_one(state.config.header_name())
+        .ok_or(CsrfError::MissingToken)?;
+    state.config.unmasks_to(submitted, token)
+}
+
+/// Form field carrying the submitted token. It is decoded when verified, as form fields have no
+/// access to the configured encoding.
+#[derive(Clone, Debug)]
+pub struct SubmittedToken(String);
+impl SubmittedToken {
+    /// Verifies that the submitted token is a `PaddedToken` of `token`, decoding it in the
+    /// encoding of `token`.
+    pub fn verify(&self, token: &CsrfToken) -> Result<(), CsrfError> {
+        token.verify(&PaddedToken::decode(&self.0, token.encoding())?)
+    }
+}
+impl<'v> FromFormField<'v> for SubmittedToken {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, SubmittedToken> {
+        Ok(SubmittedToken(field.value.to_owned()))
+    }
+}
+
+/// Decodes the field with the default `TokenEncoding`. Use `SubmittedToken` with other encodings.
+impl<'v> FromFormField<'v> for PaddedToken {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, PaddedToken> {
+        PaddedToken::decode(field.value, TokenEncoding::default())
+            .map_err(|e| form::Error::validation(e.to_string()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::form::Form;
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::blocking::Client;
+    use rocket::{get, post, routes, FromForm};
+    use super::{CsrfFairing, CsrfToken, SubmittedToken, VerifiedCsrf};
+    use crate::{CsrfConfig, PaddedToken, Token, TokenEncoding};
+
+    #[derive(FromForm)]
+    struct Comment {
+        csrf_token: SubmittedToken,
+        body: String,
+    }
+
+    #[get("/form")]
+    fn form(token: CsrfToken) -> String {
+        token.to_string()
+    }
+    #[post("/comment", data = "<comment>")]
+    fn comment(token: CsrfToken, comment: Form<Comment>) -> Result<String, Status> {
+        comment.csrf_token.verify(&token).map_err(|_| Status::Forbidden)?;
+        Ok(comment.into_inner().body)
+    }
+    #[post("/api")]
+    fn api(_csrf: VerifiedCsrf) -> &'static str {
+        "ok"
+    }
+
+    fn client(encoding: TokenEncoding) -> Client {
+        let config = CsrfConfig::new().with_secure(false).with_encoding(encoding);
+        let fairing = CsrfFairing::new(config);
+        Client::tracked(rocket::build().attach(fairing).mount("/", routes![form, comment, api]))
+            .unwrap()
+    }
+
+    #[test]
+    fn form_field_is_verified() {
+        let client = client(TokenEncoding::Base64Url);
+        let padded_token = client.get("/form").dispatch().into_string().unwrap();
+        let res = client.post("/comment")
+            .header(ContentType::Form)
+            .body(format!("body=hi&csrf_token={}", padded_token))
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.into_string().unwrap(), "hi");
+
+        let other = PaddedToken::new(&Token::new()).encode(TokenEncoding::Base64Url);
+        let res = client.post("/comment")
+            .header(ContentType::Form)
+            .body(format!("body=hi&csrf_token={}", other))
+            .dispatch();
+        assert_eq!(res.status(), Status::Forbidden);
+    }
+    #[test]
+    fn form_field_is_decoded_in_configured_encoding() {
+        let client = client(TokenEncoding::Hex);
+        let padded_token = client.get("/form").dispatch().into_string().unwrap();
+        assert!(PaddedToken::decode(&padded_token, TokenEncoding::Hex).is_ok());
+        let res = client.post("/comment")
+            .header(ContentType::Form)
+            .body(format!("body=hi&csrf_token={}", padded_token))
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+    }
+    #[test]
+    fn guard_verifies_header() {
+        let client = client(TokenEncoding::Base64Url);
+        let padded_token = client.get("/form").dispatch().into_string().unwrap();
+        assert_eq!(client.post("/api").dispatch().status(), Status::Forbidden);
+        let res = client.post("/api").header(Header::new("X-CSRF-Token", padded_token)).dispatch();
+        assert_eq!(res.status(), Status::Ok);
+    }
+}
@@ -0,0 +1,138 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Serde support. Tokens are serialized as base64 strings in human-readable formats and as
+//! their byte representation in binary formats.
+
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+use std::fmt;
+use std::marker::PhantomData;
+use crate::{CsrfError, PaddedToken, Token};
+
+/// Tokens that can be decoded from their string and byte representations.
+trait Decode: Sized {
+    const EXPECTING: &'static str;
+    fn from_str(s: &str) -> Result<Self, CsrfError>;
+    fn from_bytes(bytes: &[u8]) -> Result<Self, CsrfError>;
+}
+impl Decode for Token {
+    const EXPECTING: &'static str = "a base64 encoded token or its bytes";
+    fn from_str(s: &str) -> Result<Token, CsrfError> {
+        Token::from_base64_str(s)
+    }
+    fn from_bytes(bytes: &[u8]) -> Result<Token, CsrfError> {
+        Token::from_bytes(bytes)
+    }
+}
+impl Decode for PaddedToken {
+    const EXPECTING: &'static str = "a base64 encoded padded token or its bytes";
+    fn from_str(s: &str) -> Result<PaddedToken, CsrfError> {
+        PaddedToken::from_base64_str(s)
+    }
+    fn from_bytes(bytes: &[u8]) -> Result<PaddedToken, CsrfError> {
+        PaddedToken::from_bytes(bytes)
+    }
+}
+
+struct TokenVisitor<T>(PhantomData<T>);
+impl<'de, T: Decode> Visitor<'de> for TokenVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(T::EXPECTING)
+    }
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<T, E> {
+        T::from_str(s).map_err(E::custom)
+    }
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<T, E> {
+        T::from_bytes(bytes).map_err(E::custom)
+    }
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(64));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        self.visit_bytes(&bytes)
+    }
+}
+
+fn serialize<S: Serializer>(token: &dyn fmt::Display,
+                            bytes: &[u8],
+                            serializer: S)
+                            -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        serializer.collect_str(token)
+    } else {
+        serializer.serialize_bytes(bytes)
+    }
+}
+
+fn deserialize<'de, T: Decode, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_str(TokenVisitor(PhantomData))
+    } else {
+        deserializer.deserialize_bytes(TokenVisitor(PhantomData))
+    }
+}
+
+impl Serialize for Token {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize(self, self.as_ref(), serializer)
+    }
+}
+impl<'de> Deserialize<'de> for Token {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Token, D::Error> {
+        deserialize(deserializer)
+    }
+}
+impl Serialize for PaddedToken {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize(self, &self.to_bytes(), serializer)
+    }
+}
+impl<'de> Deserialize<'de> for PaddedToken {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<PaddedToken, D::Error> {
+        deserialize(deserializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{PaddedToken, Token};
+
+    #[test]
+    fn human_readable_formats_use_strings() {
+        let token = Token::new();
+        let padded_token = PaddedToken::new(&token);
+        let json = serde_json::to_string(&(&token, &padded_token)).unwrap();
+        assert_eq!(json, format!(r#"["{}","{}"]"#, token, padded_token));
+        let (decoded, padded): (Token, PaddedToken) = serde_json::from_str(&json).unwrap();
+        assert!(decoded == token);
+        assert!(padded.unmask() == token);
+        assert!(serde_json::from_str::<Token>(r#""AQID""#).is_err());
+    }
+    #[test]
+    fn binary_formats_use_bytes() {
+        let token = Token::new();
+        let padded_token = PaddedToken::new(&token);
+
+        let bincode = bincode::serialize(&(&token, &padded_token)).unwrap();
+        assert_eq!(bincode.len(), 8 + 32 + 8 + 64);
+        let (decoded, padded): (Token, PaddedToken) = bincode::deserialize(&bincode).unwrap();
+        assert!(decoded == token);
+        assert!(padded.unmask() == token);
+
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&(&token, &padded_token), &mut cbor).unwrap();
+        let (decoded, padded): (Token, PaddedToken) = ciborium::from_reader(&cbor[..]).unwrap();
+        assert!(decoded == token);
+        assert!(padded.unmask() == token);
+    }
+}
@@ -0,0 +1,156 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Stateless tokens signed with HMAC-SHA256.
+
+use hmac::{Hmac, Mac};
+use crate::keyring::KeyId;
+use rand::rngs::OsRng;
+use rand::{CryptoRng, RngCore};
+use sha2::Sha256;
+use crate::{CsrfError, Keyring, Token};
+
+const KEY_ID_LEN: usize = 4;
+const NONCE_LEN: usize = 16;
+/// Length of the part of the nonce that is not the key id.
+pub(crate) const NONCE_DATA_LEN: usize = NONCE_LEN - KEY_ID_LEN;
+const TAG_LEN: usize = 16;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Issues and verifies `Token`s that carry their own signature, so that nothing needs to be
+/// stored in the server session.
+///
+/// A signed token is a 256-bit `Token` made of a 128-bit nonce followed by the first 128 bits of
+/// HMAC-SHA256 over the nonce and an optional session identifier. The nonce holds the id of the
+/// signing key in its first 32 bits and random data in the rest. Signed tokens can be masked with
+/// `PaddedToken` like any other `Token`.
+pub struct TokenSigner {
+    keys: Keyring<Vec<u8>>,
+}
+impl TokenSigner {
+    /// Creates a new `TokenSigner` using `key` as the server secret.
+    pub fn new(key: &[u8]) -> TokenSigner {
+        TokenSigner::with_keyring(Keyring::new(0, key.to_vec()))
+    }
+    /// Creates a new `TokenSigner` using a `Keyring` of server secrets.
+    pub fn with_keyring(keys: Keyring<Vec<u8>>) -> TokenSigner {
+        TokenSigner { keys }
+    }
+    /// Returns the `Keyring` of this signer.
+    pub fn keyring(&self) -> &Keyring<Vec<u8>> {
+        &self.keys
+    }
+    /// Returns the `Keyring` of this signer for rotating keys.
+    pub fn keyring_mut(&mut self) -> &mut Keyring<Vec<u8>> {
+        &mut self.keys
+    }
+    /// Creates a new signed `Token`, optionally bound to a session identifier.
+    /// Panics if the random number generator fails.
+    pub fn sign(&self, session_id: Option<&[u8]>) -> Token {
+        self.try_sign(session_id).unwrap()
+    }
+    /// Creates a new signed `Token` using operating system's random number generator.
+    pub fn try_sign(&self, session_id: Option<&[u8]>) -> Result<Token, CsrfError> {
+        self.sign_with_rng(session_id, &mut OsRng)
+    }
+    /// Creates a new signed `Token` using `rng`.
+    pub fn sign_with_rng<R: RngCore + CryptoRng>(&self,
+                                                 session_id: Option<&[u8]>,
+                                                 rng: &mut R)
+                                                 -> Result<Token, CsrfError> {
+        let mut data = [0u8; NONCE_DATA_LEN];
+        rng.try_fill_bytes(&mut data).map_err(CsrfError::Rng)?;
+        Ok(self.sign_nonce(&data, session_id))
+    }
+    /// Signs a token whose nonce holds the active key id followed by `data`.
+    pub(crate) fn sign_nonce(&self,
+                             data: &[u8; NONCE_DATA_LEN],
+                             session_id: Option<&[u8]>)
+                             -> Token {
+        let (key_id, key) = self.keys.active();
+        let mut bytes = key_id.to_be_bytes().to_vec();
+        bytes.extend_from_slice(data);
+        let tag = mac(key, &bytes, session_id).finalize().into_bytes();
+        bytes.extend_from_slice(&tag[..TAG_LEN]);
+        Token(bytes)
+    }
+    /// Returns the part of the nonce of a signed `token` that is not the key id.
+    pub(crate) fn nonce_data(token: &Token) -> &[u8] {
+        &token.as_ref()[KEY_ID_LEN..NONCE_LEN]
+    }
+    /// Returns the id of the key that signed `token`, without verifying the signature.
+    pub fn key_id(token: &Token) -> Result<KeyId, CsrfError> {
+        let bytes = token.as_ref();
+        if bytes.len() != NONCE_LEN + TAG_LEN {
+            return Err(CsrfError::InvalidLength(bytes.len()));
+        }
+        let mut key_id = [0u8; KEY_ID_LEN];
+        key_id.copy_from_slice(&bytes[..KEY_ID_LEN]);
+        Ok(KeyId::from_be_bytes(key_id))
+    }
+    /// Verifies that `token` was signed with this signer's key for the given session identifier.
+    pub fn verify(&self, token: &Token, session_id: Option<&[u8]>) -> Result<(), CsrfError> {
+        let key_id = TokenSigner::key_id(token)?;
+        let (nonce, tag) = token.as_ref().split_at(NONCE_LEN);
+        let key = self.keys.get(key_id).ok_or(CsrfError::UnknownKey(key_id))?;
+        mac(key, nonce, session_id)
+            .verify_truncated_left(tag)
+            .map_err(|_| CsrfError::BadSignature)
+    }
+}
+
+fn mac(key: &[u8], nonce: &[u8], session_id: Option<&[u8]>) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(nonce);
+    if let Some(session_id) = session_id {
+        mac.update(session_id);
+    }
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CsrfError, PaddedToken, Token, TokenLength, TokenSigner};
+
+    #[test]
+    fn byte_layout_matches_fixed_vector() {
+        let signer = TokenSigner::new(b"secret");
+        let token = signer.sign_nonce(&[5; 12], Some(b"session"));
+        assert_eq!(token.to_string(), "AAAAAAUFBQUFBQUFBQUFBTFmCwo3vlrObZoUd5NRuJ8=");
+        assert!(signer.verify(&token, Some(b"session")).is_ok());
+    }
+    #[test]
+    fn signed_token_verifies_through_padded_token() {
+        let signer = TokenSigner::new(b"secret");
+        let token = signer.sign(Some(b"session"));
+        let padded_token = PaddedToken::from_base64_str(&PaddedToken::new(&token).to_string())
+            .unwrap();
+        assert!(signer.verify(&padded_token.unmask(), Some(b"session")).is_ok());
+    }
+    #[test]
+    fn signed_token_without_session() {
+        let signer = TokenSigner::new(b"secret");
+        let token = signer.sign(None);
+        assert!(signer.verify(&token, None).is_ok());
+        assert!(signer.verify(&token, Some(b"session")).is_err());
+    }
+    #[test]
+    fn wrong_key_session_or_token_is_rejected() {
+        let signer = TokenSigner::new(b"secret");
+        let token = signer.sign(Some(b"session"));
+        assert!(matches!(TokenSigner::new(b"other").verify(&token, Some(b"session")),
+                         Err(CsrfError::BadSignature)));
+        assert!(matches!(signer.verify(&token, Some(b"other")), Err(CsrfError::BadSignature)));
+        assert!(matches!(signer.verify(&Token::new(), Some(b"session")),
+                         Err(CsrfError::UnknownKey(_))));
+        assert!(matches!(signer.verify(&Token::with_length(TokenLength::Bits128), Some(b"session")),
+                         Err(CsrfError::InvalidLength(16))));
+    }
+}
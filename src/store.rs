@@ -0,0 +1,287 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Synchronizer token pattern.
+
+use crate::expiry::SystemClock;
+use rand::rngs::OsRng;
+use rand::{CryptoRng, RngCore};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use crate::{Clock, CsrfError, ExpiryPolicy, PaddedToken, Token, TokenLength};
+
+/// Storage for the `Token`s of each session.
+pub trait TokenStore {
+    /// Error of the storage backend.
+    type Error: Error + Send + Sync + 'static;
+
+    /// Returns the `Token` stored for `session_id`.
+    fn get(&self, session_id: &[u8]) -> Result<Option<Token>, Self::Error>;
+    /// Stores `token` for `session_id`, replacing any previous token.
+    fn put(&self, session_id: &[u8], token: Token) -> Result<(), Self::Error>;
+    /// Returns the `Token` stored for `session_id`, storing `token` first if there is none.
+    /// Must be atomic, so that concurrent calls for a session all return the same `Token`.
+    fn get_or_insert(&self, session_id: &[u8], token: Token) -> Result<Token, Self::Error>;
+    /// Removes the `Token` stored for `session_id`.
+    fn remove(&self, session_id: &[u8]) -> Result<(), Self::Error>;
+}
+
+const EVICTION_INTERVAL: usize = 1024;
+
+/// In-memory `TokenStore` that evicts tokens once they are older than the time to live of its
+/// `ExpiryPolicy`. Expired tokens are never returned, and are removed from memory every
+/// 1024 stores by default or whenever `evict_expired` is called.
+pub struct InMemoryTokenStore<C = SystemClock> {
+    tokens: Mutex<HashMap<Vec<u8>, (Token, u64)>>,
+    expiry: ExpiryPolicy<C>,
+    puts: AtomicUsize,
+    eviction_interval: usize,
+}
+impl InMemoryTokenStore<SystemClock> {
+    /// Creates a new `InMemoryTokenStore` that keeps tokens for `ttl`.
+    pub fn new(ttl: Duration) -> InMemoryTokenStore<SystemClock> {
+        InMemoryTokenStore::with_expiry(ExpiryPolicy::new(ttl))
+    }
+}
+impl<C: Clock> InMemoryTokenStore<C> {
+    /// Creates a new `InMemoryTokenStore` using the given `ExpiryPolicy`.
+    pub fn with_expiry(expiry: ExpiryPolicy<C>) -> InMemoryTokenStore<C> {
+        InMemoryTokenStore {
+            tokens: Mutex::new(HashMap::new()),
+            expiry,
+            puts: AtomicUsize::new(0),
+            eviction_interval: EVICTION_INTERVAL,
+        }
+    }
+    /// Sets after how many stored tokens expired tokens are evicted. An interval of `0`
+    /// disables automatic eviction.
+    pub fn with_eviction_interval(mut self, puts: usize) -> InMemoryTokenStore<C> {
+        self.eviction_interval = puts;
+        self
+    }
+    /// Returns the number of stored tokens, including expired tokens not yet evicted.
+    pub fn len(&self) -> usize {
+        self.tokens.lock().unwrap().len()
+    }
+    /// Returns `true` if no tokens are stored.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Evicts all expired tokens.
+    pub fn evict_expired(&self) {
+        self.evict_expired_from(&mut self.tokens.lock().unwrap());
+    }
+    fn evict_expired_from(&self, tokens: &mut HashMap<Vec<u8>, (Token, u64)>) {
+        tokens.retain(|_, &mut (_, issued_at)| self.expiry.is_fresh_at(issued_at));
+    }
+    /// Stores `token`, evicting expired tokens every `eviction_interval` stores.
+    fn insert(&self, tokens: &mut HashMap<Vec<u8>, (Token, u64)>, session_id: &[u8], token: Token) {
+        let puts = self.puts.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.eviction_interval != 0 && puts.is_multiple_of(self.eviction_interval) {
+            self.evict_expired_from(tokens);
+        }
+        tokens.insert(session_id.to_vec(), (token, self.expiry.now()));
+    }
+}
+impl<C: Clock> TokenStore for InMemoryTokenStore<C> {
+    type Error = Infallible;
+
+    fn get(&self, session_id: &[u8]) -> Result<Option<Token>, Infallible> {
+        let mut tokens = self.tokens.lock().unwrap();
+        let fresh = match tokens.get(session_id) {
+            Some(&(_, issued_at)) => self.expiry.is_fresh_at(issued_at),
+            None => return Ok(None),
+        };
+        if fresh {
+            Ok(tokens.get(session_id).map(|(token, _)| token.clone()))
+        } else {
+            tokens.remove(session_id);
+            Ok(None)
+        }
+    }
+    fn put(&self, session_id: &[u8], token: Token) -> Result<(), Infallible> {
+        self.insert(&mut self.tokens.lock().unwrap(), session_id, token);
+        Ok(())
+    }
+    fn get_or_insert(&self, session_id: &[u8], token: Token) -> Result<Token, Infallible> {
+        let mut tokens = self.tokens.lock().unwrap();
+        match tokens.get(session_id) {
+            Some((stored, issued_at)) if self.expiry.is_fresh_at(*issued_at) => Ok(stored.clone()),
+            _ => {
+                self.insert(&mut tokens, session_id, token.clone());
+                Ok(token)
+            }
+        }
+    }
+    fn remove(&self, session_id: &[u8]) -> Result<(), Infallible> {
+        self.tokens.lock().unwrap().remove(session_id);
+        Ok(())
+    }
+}
+
+/// Wraps an error of a `TokenStore`.
+fn store_error<E: Error + Send + Sync + 'static>(e: E) -> CsrfError {
+    CsrfError::Store(Box::new(e))
+}
+
+/// Synchronizer token pattern. Each session has a `Token` kept in a `TokenStore`, and requests
+/// are accepted when the submitted `PaddedToken` unmasks to it.
+pub struct SynchronizerToken<S> {
+    store: S,
+}
+impl<S: TokenStore> SynchronizerToken<S> {
+    /// Creates a new `SynchronizerToken` backed by `store`.
+    pub fn new(store: S) -> SynchronizerToken<S> {
+        SynchronizerToken { store }
+    }
+    /// Returns the underlying `TokenStore`.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+    /// Returns a new `PaddedToken` for `session_id`, creating and storing a `Token` for the
+    /// session if it does not have one. Panics if the random number generator or the store
+    /// fails.
+    pub fn issue(&self, session_id: &[u8]) -> PaddedToken {
+        self.try_issue(session_id).unwrap()
+    }
+    /// Returns a new `PaddedToken` for `session_id` using operating system's random number
+    /// generator.
+    pub fn try_issue(&self, session_id: &[u8]) -> Result<PaddedToken, CsrfError> {
+        self.issue_with_rng(session_id, &mut OsRng)
+    }
+    /// Returns a new `PaddedToken` for `session_id` using `rng`.
+    pub fn issue_with_rng<R: RngCore + CryptoRng>(&self,
+                                                  session_id: &[u8],
+                                                  rng: &mut R)
+                                                  -> Result<PaddedToken, CsrfError> {
+        let token = Token::with_rng(TokenLength::default(), rng)?;
+        let token = self.store.get_or_insert(session_id, token).map_err(store_error)?;
+        PaddedToken::with_rng(&token, rng)
+    }
+    /// Verifies `padded_token` against the `Token` stored for `session_id`.
+    pub fn verify(&self, session_id: &[u8], padded_token: &PaddedToken) -> Result<(), CsrfError> {
+        let token = self.store
+            .get(session_id)
+            .map_err(store_error)?
+            .ok_or(CsrfError::UnknownSession)?;
+        if padded_token.unmask() != token {
+            return Err(CsrfError::TokenMismatch);
+        }
+        Ok(())
+    }
+    /// Removes the `Token` of `session_id`, for example when the session ends.
+    pub fn revoke(&self, session_id: &[u8]) -> Result<(), CsrfError> {
+        self.store.remove(session_id).map_err(store_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::time::Duration;
+    use crate::expiry::ManualClock;
+    use crate::{CsrfError, ExpiryPolicy, InMemoryTokenStore, SynchronizerToken, Token, TokenStore};
+
+    /// `TokenStore` whose backend is unavailable.
+    struct FailingStore;
+    impl TokenStore for FailingStore {
+        type Error = io::Error;
+
+        fn get(&self, _: &[u8]) -> Result<Option<Token>, io::Error> {
+            Err(io::ErrorKind::ConnectionRefused.into())
+        }
+        fn put(&self, _: &[u8], _: Token) -> Result<(), io::Error> {
+            Err(io::ErrorKind::ConnectionRefused.into())
+        }
+        fn get_or_insert(&self, _: &[u8], _: Token) -> Result<Token, io::Error> {
+            Err(io::ErrorKind::ConnectionRefused.into())
+        }
+        fn remove(&self, _: &[u8]) -> Result<(), io::Error> {
+            Err(io::ErrorKind::ConnectionRefused.into())
+        }
+    }
+
+    #[test]
+    fn put_get_and_remove() {
+        let store = InMemoryTokenStore::new(Duration::from_secs(60));
+        let token = Token::new();
+        store.put(b"session", token.clone()).unwrap();
+        assert!(store.get(b"session").unwrap() == Some(token));
+        assert!(store.get(b"other").unwrap().is_none());
+        store.remove(b"session").unwrap();
+        assert!(store.get(b"session").unwrap().is_none());
+    }
+    #[test]
+    fn get_or_insert_keeps_stored_token() {
+        let clock = ManualClock::new(1000);
+        let expiry = ExpiryPolicy::with_clock(Duration::from_secs(60), &clock);
+        let store = InMemoryTokenStore::with_expiry(expiry);
+        let (first, second) = (Token::new(), Token::new());
+        assert!(store.get_or_insert(b"session", first.clone()).unwrap() == first);
+        assert!(store.get_or_insert(b"session", second.clone()).unwrap() == first);
+        clock.set(1061);
+        assert!(store.get_or_insert(b"session", second.clone()).unwrap() == second);
+    }
+    #[test]
+    fn expired_tokens_are_evicted() {
+        let clock = ManualClock::new(1000);
+        let expiry = ExpiryPolicy::with_clock(Duration::from_secs(60), &clock);
+        let store = InMemoryTokenStore::with_expiry(expiry);
+        store.put(b"first", Token::new()).unwrap();
+        clock.set(1030);
+        store.put(b"second", Token::new()).unwrap();
+        clock.set(1061);
+        assert!(store.get(b"first").unwrap().is_none());
+        assert_eq!(store.len(), 1);
+        store.put(b"third", Token::new()).unwrap();
+        assert_eq!(store.len(), 2);
+        clock.set(1200);
+        store.evict_expired();
+        assert!(store.is_empty());
+    }
+    #[test]
+    fn expired_tokens_are_evicted_periodically() {
+        let clock = ManualClock::new(1000);
+        let expiry = ExpiryPolicy::with_clock(Duration::from_secs(60), &clock);
+        let store = InMemoryTokenStore::with_expiry(expiry).with_eviction_interval(3);
+        store.put(b"first", Token::new()).unwrap();
+        store.put(b"second", Token::new()).unwrap();
+        clock.set(1061);
+        store.put(b"third", Token::new()).unwrap();
+        assert_eq!(store.len(), 1);
+        store.put(b"fourth", Token::new()).unwrap();
+        assert_eq!(store.len(), 2);
+    }
+    #[test]
+    fn synchronizer_token_verifies_against_store() {
+        let synchronizer = SynchronizerToken::new(InMemoryTokenStore::new(Duration::from_secs(60)));
+        let first = synchronizer.issue(b"session");
+        let second = synchronizer.issue(b"session");
+        assert!(first != second);
+        assert!(synchronizer.verify(b"session", &first).is_ok());
+        assert!(synchronizer.verify(b"session", &second).is_ok());
+        assert!(matches!(synchronizer.verify(b"other", &first), Err(CsrfError::UnknownSession)));
+        synchronizer.issue(b"other");
+        assert!(matches!(synchronizer.verify(b"other", &first), Err(CsrfError::TokenMismatch)));
+        synchronizer.revoke(b"session").unwrap();
+        assert!(synchronizer.verify(b"session", &first).is_err());
+    }
+    #[test]
+    fn store_failures_are_reported() {
+        let synchronizer = SynchronizerToken::new(FailingStore);
+        assert!(matches!(synchronizer.try_issue(b"session"), Err(CsrfError::Store(_))));
+        let padded_token = crate::PaddedToken::new(&Token::new());
+        assert!(matches!(synchronizer.verify(b"session", &padded_token), Err(CsrfError::Store(_))));
+        assert!(matches!(synchronizer.revoke(b"session"), Err(CsrfError::Store(_))));
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Tera integration. After `register`, templates render the hidden form input with
+//! `{{ csrf_field(token=csrf_token) }}`, where `csrf_token` is inserted by `insert_token`. The
+//! token is rendered as inserted, in the encoding of the `CsrfToken`.
+
+use std::collections::HashMap;
+use tera::{Context, Function, Tera, Value};
+use crate::{CsrfConfig, CsrfToken, PaddedToken, TokenEncoding};
+
+/// Encodings a `token` argument is accepted in.
+const ENCODINGS: [TokenEncoding; 4] = [TokenEncoding::Base64,
+                                       TokenEncoding::Base64Url,
+                                       TokenEncoding::Hex,
+                                       TokenEncoding::Crockford32];
+
+/// Tera function rendering the hidden form input for the `PaddedToken` in its `token` argument.
+pub struct CsrfField {
+    config: CsrfConfig,
+}
+impl CsrfField {
+    /// Creates a new `CsrfField` rendering the form field configured in `config`.
+    pub fn new(config: CsrfConfig) -> CsrfField {
+        CsrfField { config }
+    }
+}
+impl Function for CsrfField {
+    fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let token = match args.get("token") {
+            Some(Value::String(token)) => token,
+            _ => return Err("csrf_field requires a `token` string argument".into()),
+        };
+        if !ENCODINGS.iter().any(|&encoding| PaddedToken::decode(token, encoding).is_ok()) {
+            return Err("csrf_field requires an encoded `PaddedToken` as `token` argument".into());
+        }
+        Ok(Value::String(self.config.encoded_hidden_input(token).to_string()))
+    }
+
+    fn is_safe(&self) -> bool {
+        true
+    }
+}
+
+/// Registers a `CsrfField` as the `csrf_field` function of `tera`.
+pub fn register(tera: &mut Tera, config: CsrfConfig) {
+    tera.register_function("csrf_field", CsrfField::new(config));
+}
+
+/// Inserts the token of the current request into `context` as `csrf_token`.
+pub fn insert_token(context: &mut Context, token: &CsrfToken) {
+    context.insert("csrf_token", &token.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use tera::{Context, Tera};
+    use super::{insert_token, register};
+    use crate::{CsrfConfig, CsrfToken, Token, TokenEncoding};
+
+    #[test]
+    fn renders_hidden_input() {
+        let config = CsrfConfig::new().with_field_name("_csrf");
+        let mut tera = Tera::default();
+        tera.add_raw_template("form.html", "<form>{{ csrf_field(token=csrf_token) }}</form>")
+            .unwrap();
+        register(&mut tera, config.clone());
+
+        let token = CsrfToken::new(&Token::new());
+        let mut context = Context::new();
+        insert_token(&mut context, &token);
+        assert_eq!(tera.render("form.html", &context).unwrap(),
+                   format!("<form>{}</form>", config.hidden_input(token.padded_token())));
+        context.insert("csrf_token", "<script>");
+        assert!(tera.render("form.html", &context).is_err());
+    }
+    #[test]
+    fn renders_token_in_its_own_encoding() {
+        let config = CsrfConfig::new().with_encoding(TokenEncoding::Crockford32);
+        let mut tera = Tera::default();
+        tera.add_raw_template("form.html", "{{ csrf_field(token=csrf_token) }}").unwrap();
+        register(&mut tera, config.clone());
+
+        let token = CsrfToken::new(&Token::new()).with_encoding(TokenEncoding::Hex);
+        let mut context = Context::new();
+        insert_token(&mut context, &token);
+        let expected = config.with_encoding(TokenEncoding::Hex);
+        assert_eq!(tera.render("form.html", &context).unwrap(),
+                   expected.hidden_input(token.padded_token()).to_string());
+    }
+}
@@ -0,0 +1,375 @@
+// Copyright (c) 2016 csrf developers
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>,
+// at your option. All files in the project carrying such
+// notice may not be copied, modified, or distributed except
+// according to those terms.
+
+//! Tower middleware using the double-submit cookie strategy.
+
+use bytes::Bytes;
+use http::header::{CONTENT_TYPE, COOKIE, SET_COOKIE};
+use http::request::Parts;
+use http::{HeaderMap, HeaderValue, Request, Response, StatusCode};
+use http_body::Body;
+use http_body_util::BodyExt;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower_layer::Layer;
+use tower_service::Service;
+use crate::body::{extract_token, read_body, BodyKind};
+use crate::config::http_status;
+use crate::{is_safe_method, CsrfConfig, CsrfError, DoubleSubmitCookie};
+
+pub use crate::CsrfToken;
+
+/// Function returning the session identifier of a request, if it has one.
+type SessionId = dyn Fn(&Parts) -> Option<Vec<u8>> + Send + Sync;
+
+struct State {
+    config: CsrfConfig,
+    double_submit: DoubleSubmitCookie,
+}
+
+/// Verification state of the current request, inserted into the request extensions so that
+/// the axum extractors can verify requests the middleware left unverified.
+#[cfg(feature = "axum")]
+#[derive(Clone)]
+pub(crate) struct Verification {
+    state: Arc<State>,
+    cookie: Option<String>,
+    session_id: Option<Vec<u8>>,
+    verified: bool,
+}
+#[cfg(feature = "axum")]
+impl Verification {
+    /// Verifies the token submitted in the configured header, unless the middleware has already
+    /// verified the request.
+    pub(crate) fn verify(&self, headers: &HeaderMap) -> Result<(), CsrfError> {
+        if self.verified {
+            return Ok(());
+        }
+        let cookie = self.cookie.as_deref().ok_or(CsrfError::MissingToken)?;
+        let config = &self.state.config;
+        let header_value = headers.get(config.header_name()).map(|v| v.as_bytes());
+        let submitted = config.header_token(header_value)?.ok_or(CsrfError::MissingToken)?;
+        let session_id = self.session_id.as_deref();
+        self.state.double_submit.verify_with(cookie, &submitted, session_id, config.encoding())
+    }
+}
+
+/// `Layer` that wraps services in a `CsrfService`.
+#[derive(Clone)]
+pub struct CsrfLayer {
+    state: Arc<State>,
+    session_id: Option<Arc<SessionId>>,
+    verify_unsafe: bool,
+}
+impl CsrfLayer {
+    /// Creates a new `CsrfLayer` using the names in `config` and the given strategy.
+    pub fn new(config: CsrfConfig, double_submit: DoubleSubmitCookie) -> CsrfLayer {
+        CsrfLayer {
+            state: Arc::new(State { config, double_submit }),
+            session_id: None,
+            verify_unsafe: true,
+        }
+    }
+    /// Sets the function returning the session identifier of a request. Cookie tokens of a
+    /// signed `DoubleSubmitCookie` are bound to this identifier when issued and verified, so
+    /// that a cookie issued to one session is rejected in another.
+    pub fn with_session_id<F>(mut self, session_id: F) -> CsrfLayer
+        where F: Fn(&Parts) -> Option<Vec<u8>> + Send + Sync + 'static
+    {
+        self.session_id = Some(Arc::new(session_id));
+        self
+    }
+    /// Sets whether unsafe requests are verified by the middleware. When disabled, the
+    /// middleware only issues tokens and verification is left to the handlers.
+    pub fn verify_unsafe(mut self, verify: bool) -> CsrfLayer {
+        self.verify_unsafe = verify;
+        self
+    }
+}
+impl Default for CsrfLayer {
+    fn default() -> CsrfLayer {
+        CsrfLayer::new(CsrfConfig::new(), DoubleSubmitCookie::new())
+    }
+}
+impl<S> Layer<S> for CsrfLayer {
+    type Service = CsrfService<S>;
+
+    fn layer(&self, inner: S) -> CsrfService<S> {
+        CsrfService {
+            inner,
+            state: self.state.clone(),
+            session_id: self.session_id.clone(),
+            verify_unsafe: self.verify_unsafe,
+        }
+    }
+}
+
+/// Middleware that issues a token cookie on safe requests when the client has none, and
+/// verifies the token submitted in the configured header or request body field on unsafe
+/// requests. Requests that fail verification are answered with `403 Forbidden`, or with
+/// `413 Payload Too Large` and `400 Bad Request` when the body cannot be read. The `CsrfError`
+/// is available to outer services as an `Arc<CsrfError>` in the response extensions.
+///
+/// The `CsrfToken` of the request is available to the inner service in the request extensions.
+#[derive(Clone)]
+pub struct CsrfService<S> {
+    inner: S,
+    state: Arc<State>,
+    session_id: Option<Arc<SessionId>>,
+    verify_unsafe: bool,
+}
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for CsrfService<S>
+    where S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+          S::Future: Send,
+          ReqBody: Body + From<Bytes> + Send + 'static,
+          ReqBody::Data: Send,
+          ReqBody::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+          ResBody: Default + Send + 'static
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<ResBody>, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = mem::replace(&mut self.inner, clone);
+        let state = self.state.clone();
+        let (parts, body) = req.into_parts();
+        let session_id = self.session_id.as_ref().and_then(|session_id| session_id(&parts));
+        let req = Request::from_parts(parts, body);
+        let verify_unsafe = self.verify_unsafe;
+        Box::pin(async move {
+            let cookie = cookie_value(&state.config, req.headers());
+            let verified = verify_unsafe && !is_safe_method(req.method().as_str());
+            let mut req = if verified {
+                match verify(&state, cookie.as_deref(), session_id.as_deref(), req).await {
+                    Ok(req) => req,
+                    Err(e) => return Ok(error_response(e)),
+                }
+            } else {
+                req
+            };
+            let valid = cookie.as_deref().map(|cookie| {
+                let encoding = state.config.encoding();
+                state.double_submit.verify_cookie_with(cookie, session_id.as_deref(), encoding)
+            });
+            let issued = match valid {
+                Some(Ok(token)) => Ok((token, false)),
+                _ => {
+                    let issued = state.double_submit.try_issue(session_id.as_deref());
+                    issued.map(|(token, _)| (token, true))
+                }
+            };
+            let csrf_token = issued.and_then(|(token, issued)| {
+                let csrf_token = CsrfToken::try_new(&token)?;
+                Ok((token, issued, csrf_token.with_encoding(state.config.encoding())))
+            });
+            let (token, issued, csrf_token) = match csrf_token {
+                Ok(issued) => issued,
+                Err(e) => return Ok(error_response(e)),
+            };
+            req.extensions_mut().insert(csrf_token);
+            #[cfg(feature = "axum")]
+            req.extensions_mut().insert(Verification {
+                state: state.clone(),
+                cookie,
+                session_id,
+                verified,
+            });
+            let mut res = inner.call(req).await?;
+            if issued {
+                let set_cookie = state.config.set_cookie(&token);
+                if let Ok(value) = HeaderValue::from_str(&set_cookie) {
+                    res.headers_mut().append(SET_COOKIE, value);
+                }
+            }
+            Ok(res)
+        })
+    }
+}
+
+/// Returns the response for a request that failed with `error`.
+fn error_response<B: Default>(error: CsrfError) -> Response<B> {
+    let mut res = Response::new(B::default());
+    *res.status_mut() =
+        StatusCode::from_u16(http_status(&error)).unwrap_or(StatusCode::FORBIDDEN);
+    res.extensions_mut().insert(Arc::new(error));
+    res
+}
+
+fn cookie_value(config: &CsrfConfig, headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .find_map(|header| config.cookie_value(header))
+        .map(String::from)
+}
+
+/// Verifies the token submitted with an unsafe request, returning the request with its body
+/// intact.
+async fn verify<B>(state: &State,
+                   cookie: Option<&str>,
+                   session_id: Option<&[u8]>,
+                   req: Request<B>)
+                   -> Result<Request<B>, CsrfError>
+    where B: Body + From<Bytes>,
+          B::Error: Into<Box<dyn std::error::Error + Send + Sync>>
+{
+    let cookie = cookie.ok_or(CsrfError::MissingToken)?;
+    let header_value = req.headers().get(state.config.header_name()).map(|v| v.as_bytes());
+    let (req, submitted) = match state.config.header_token(header_value)? {
+        Some(submitted) => (req, submitted),
+        None => {
+            let kind = req.headers()
+                .get(CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(BodyKind::from_content_type)
+                .ok_or(CsrfError::MissingToken)?;
+            let (parts, body) = req.into_parts();
+            let bytes = read_body(&state.config, body.into_data_stream()).await?;
+            let submitted = extract_token(&state.config, &kind, &bytes).await?;
+            (Request::from_parts(parts, B::from(bytes)), submitted)
+        }
+    };
+    state.double_submit.verify_with(cookie, &submitted, session_id, state.config.encoding())?;
+    Ok(req)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use http::header::{CONTENT_TYPE, COOKIE, SET_COOKIE};
+    use http::{Request, Response, StatusCode};
+    use http_body_util::{BodyExt, Full};
+    use std::convert::Infallible;
+    use std::sync::Arc;
+    use tower::{service_fn, Layer, ServiceExt};
+    use super::{CsrfLayer, CsrfToken};
+    use crate::{CsrfConfig, CsrfError, DoubleSubmitCookie, Token, TokenEncoding};
+
+    async fn echo(req: Request<Full<Bytes>>) -> Result<Response<String>, Infallible> {
+        let token = req.extensions().get::<CsrfToken>().unwrap().to_string();
+        let body = req.into_body().collect().await.unwrap().to_bytes();
+        Ok(Response::new(format!("{} {}", token, String::from_utf8_lossy(&body))))
+    }
+
+    fn request(method: &str, cookie: Option<&Token>) -> http::request::Builder {
+        let builder = Request::builder().method(method).uri("/");
+        match cookie {
+            Some(token) => {
+                let cookie = format!("csrf_token={}", token.encode(TokenEncoding::Base64Url));
+                builder.header(COOKIE, cookie)
+            }
+            None => builder,
+        }
+    }
+
+    #[tokio::test]
+    async fn safe_request_issues_cookie() {
+        let service = CsrfLayer::default().layer(service_fn(echo));
+        let req = request("GET", None).body(Full::default()).unwrap();
+        let res = service.clone().oneshot(req).await.unwrap();
+        let set_cookie = res.headers()[SET_COOKIE].to_str().unwrap();
+        let value = CsrfConfig::new().cookie_value(&set_cookie[..set_cookie.find(';').unwrap()]);
+        let token = Token::decode(value.unwrap(), TokenEncoding::Base64Url).unwrap();
+
+        let req = request("GET", Some(&token)).body(Full::default()).unwrap();
+        let res = service.oneshot(req).await.unwrap();
+        assert!(res.headers().get(SET_COOKIE).is_none());
+    }
+    #[tokio::test]
+    async fn unsafe_request_is_verified_from_header() {
+        let service = CsrfLayer::default().layer(service_fn(echo));
+        let (token, padded_token) = DoubleSubmitCookie::new().issue(None);
+        let req = request("POST", Some(&token))
+            .header("X-CSRF-Token", padded_token.encode(TokenEncoding::Base64Url))
+            .body(Full::default())
+            .unwrap();
+        assert_eq!(service.clone().oneshot(req).await.unwrap().status(), StatusCode::OK);
+
+        let (other, _) = DoubleSubmitCookie::new().issue(None);
+        let req = request("POST", Some(&other))
+            .header("X-CSRF-Token", padded_token.encode(TokenEncoding::Base64Url))
+            .body(Full::default())
+            .unwrap();
+        assert_eq!(service.clone().oneshot(req).await.unwrap().status(), StatusCode::FORBIDDEN);
+
+        let req = request("DELETE", Some(&token)).body(Full::default()).unwrap();
+        assert_eq!(service.oneshot(req).await.unwrap().status(), StatusCode::FORBIDDEN);
+    }
+    #[tokio::test]
+    async fn unsafe_request_is_verified_from_body() {
+        let config = CsrfConfig::new().with_field_name("_csrf").with_body_limit(128);
+        let service = CsrfLayer::new(config, DoubleSubmitCookie::new()).layer(service_fn(echo));
+        let (token, padded_token) = DoubleSubmitCookie::new().issue(None);
+        let form = format!("name=x&_csrf={}", padded_token.encode(TokenEncoding::Base64Url));
+        let req = request("POST", Some(&token))
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Full::from(form.clone()))
+            .unwrap();
+        let res = service.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.into_body().ends_with(&form));
+
+        let json = format!(r#"{{"name": "x", "csrf_token": "{}"}}"#,
+                           padded_token.encode(TokenEncoding::Base64Url));
+        let req = request("POST", Some(&token))
+            .header(CONTENT_TYPE, "application/json")
+            .body(Full::from(json.clone()))
+            .unwrap();
+        let res = service.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.into_body().ends_with(&json));
+
+        let req = request("POST", Some(&token))
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Full::from(format!("{}&padding={}", form, "x".repeat(128))))
+            .unwrap();
+        let res = service.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let error = res.extensions().get::<Arc<CsrfError>>().unwrap();
+        assert!(matches!(**error, CsrfError::BodyTooLarge(128)));
+
+        let req = request("POST", Some(&token))
+            .header(CONTENT_TYPE, "application/json")
+            .body(Full::from("{"))
+            .unwrap();
+        assert_eq!(service.oneshot(req).await.unwrap().status(), StatusCode::BAD_REQUEST);
+    }
+    #[tokio::test]
+    async fn signed_cookie_is_bound_to_session() {
+        let layer = CsrfLayer::new(CsrfConfig::new(), DoubleSubmitCookie::signed(b"secret"))
+            .with_session_id(|parts| parts.headers.get("x-session").map(|v| v.as_bytes().to_vec()));
+        let service = layer.layer(service_fn(echo));
+        let req = Request::builder().header("x-session", "a").body(Full::default()).unwrap();
+        let res = service.clone().oneshot(req).await.unwrap();
+        let set_cookie = res.headers()[SET_COOKIE].to_str().unwrap().to_owned();
+        let cookie = set_cookie[..set_cookie.find(';').unwrap()].to_owned();
+        let padded_token = res.into_body().trim().to_owned();
+
+        for (session, status) in [("a", StatusCode::OK), ("b", StatusCode::FORBIDDEN)] {
+            let req = Request::builder()
+                .method("POST")
+                .header(COOKIE, cookie.as_str())
+                .header("x-session", session)
+                .header("X-CSRF-Token", padded_token.as_str())
+                .body(Full::default())
+                .unwrap();
+            assert_eq!(service.clone().oneshot(req).await.unwrap().status(), status);
+        }
+    }
+}